@@ -0,0 +1,176 @@
+use std::{env, str::FromStr, sync::{Arc, RwLock}};
+
+use chrono::NaiveTime;
+use pml::PmlStruct;
+
+pub const CONFIG_FILE: &str = "config.pml";
+
+#[derive(Clone)]
+pub struct Config {
+    pub bot_token: String,
+    pub db_file: String,
+    pub guild_id: u64,
+    pub channel_zitate: u64,
+    pub channel_bot: u64,
+    pub channel_daily: Option<u64>,
+    pub daily_time: Option<NaiveTime>,
+    pub owner_id: u64,
+    pub reminder_days: Option<u64>,
+    pub owner_reminder_days: Option<u64>,
+}
+
+// Every key can be overridden by an environment variable, e.g. botToken by ZITATE_BOT_TOKEN
+fn env_name(key: &str) -> String {
+    let mut name = String::from("ZITATE_");
+    for c in key.chars() {
+        if c.is_uppercase() {
+            name.push('_');
+        }
+        name.push(c.to_ascii_uppercase());
+    }
+    name
+}
+
+struct Reader {
+    file: Option<PmlStruct>,
+    errors: Vec<String>,
+}
+
+impl Reader {
+    fn raw(&self, key: &str) -> Option<String> {
+        env::var(env_name(key))
+            .ok()
+            .or_else(|| self.file.as_ref().and_then(|file| file.get::<String>(key)))
+    }
+
+    fn optional<T: FromStr>(&mut self, key: &str, expected: &str) -> Option<T> {
+        let value = self.raw(key)?;
+        match value.trim().parse() {
+            Ok(value) => Some(value),
+            Err(_) => {
+                self.errors.push(format!("{key} must be {expected}, found \"{value}\""));
+                None
+            }
+        }
+    }
+
+    fn required<T: FromStr>(&mut self, key: &str, expected: &str) -> Option<T> {
+        if self.raw(key).is_none() {
+            self.errors.push(format!("{key} value not found in config file (or {})", env_name(key)));
+            return None;
+        }
+        self.optional(key, expected)
+    }
+
+    fn time(&mut self, key: &str) -> Option<NaiveTime> {
+        let value = self.raw(key)?;
+        match NaiveTime::parse_from_str(value.trim(), "%H:%M") {
+            Ok(time) => Some(time),
+            Err(_) => {
+                self.errors.push(format!("{key} must have the format HH:MM, found \"{value}\""));
+                None
+            }
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &str) -> Result<Self, Vec<String>> {
+        let mut reader = Reader {
+            file: None,
+            errors: Vec::new(),
+        };
+        match pml::parse::file(path) {
+            Ok(file) => reader.file = Some(file),
+            Err(why) => reader.errors.push(format!("Error parsing {path}: {why:?}")),
+        }
+        let bot_token = reader.required("botToken", "a string");
+        let db_file = reader.required("dbFile", "a string");
+        let guild_id = reader.required("guildId", "an ID");
+        let channel_zitate = reader.required("channelZitate", "an ID");
+        let channel_bot = reader.required("channelBot", "an ID");
+        let channel_daily = reader.optional("channelDaily", "an ID");
+        let daily_time = reader.time("dailyTime");
+        let owner_id = reader.required("ownerId", "an ID");
+        let reminder_days = reader.optional("reminderDays", "a number of days");
+        let owner_reminder_days = reader.optional("ownerReminderDays", "a number of days");
+        if channel_daily.is_some() && reader.raw("dailyTime").is_none() {
+            reader.errors.push(String::from("dailyTime value not found in config file, but channelDaily is set"));
+        }
+
+        match (bot_token, db_file, guild_id, channel_zitate, channel_bot, owner_id) {
+            (Some(bot_token), Some(db_file), Some(guild_id), Some(channel_zitate), Some(channel_bot), Some(owner_id))
+                if reader.errors.is_empty() =>
+            {
+                Ok(Self {
+                    bot_token,
+                    db_file,
+                    guild_id,
+                    channel_zitate,
+                    channel_bot,
+                    channel_daily,
+                    daily_time,
+                    owner_id,
+                    reminder_days,
+                    owner_reminder_days,
+                })
+            }
+            _ => Err(reader.errors),
+        }
+    }
+
+    fn changed_keys(&self, other: &Self) -> Vec<&'static str> {
+        [
+            ("botToken", self.bot_token != other.bot_token),
+            ("dbFile", self.db_file != other.db_file),
+            ("guildId", self.guild_id != other.guild_id),
+            ("channelZitate", self.channel_zitate != other.channel_zitate),
+            ("channelBot", self.channel_bot != other.channel_bot),
+            ("channelDaily", self.channel_daily != other.channel_daily),
+            ("dailyTime", self.daily_time != other.daily_time),
+            ("ownerId", self.owner_id != other.owner_id),
+            ("reminderDays", self.reminder_days != other.reminder_days),
+            ("ownerReminderDays", self.owner_reminder_days != other.owner_reminder_days),
+        ]
+        .into_iter()
+        .filter(|(_, changed)| *changed)
+        .map(|(key, _)| key)
+        .collect()
+    }
+}
+
+// Handed to every task; reload swaps the whole Config so readers never see a half-updated one
+#[derive(Clone)]
+pub struct SharedConfig(Arc<RwLock<Arc<Config>>>);
+
+impl SharedConfig {
+    pub fn new(config: Config) -> Self {
+        Self(Arc::new(RwLock::new(Arc::new(config))))
+    }
+
+    pub fn get(&self) -> Arc<Config> {
+        self.0.read().unwrap().clone()
+    }
+
+    /// `apply` runs before the swap, if it fails the old Config stays in place.
+    pub fn reload(
+        &self,
+        path: &str,
+        apply: impl FnOnce(&Config, &[&'static str]) -> Result<(), String>,
+    ) -> Result<Vec<&'static str>, Vec<String>> {
+        let new_config = Config::load(path)?;
+        let mut current = self.0.write().unwrap();
+        let changed = current.changed_keys(&new_config);
+        let fixed: Vec<String> = changed
+            .iter()
+            .filter(|key| ["botToken", "dbFile", "guildId"].contains(key))
+            .map(|key| format!("{key} cannot be changed while the bot is running, restart it instead"))
+            .collect();
+        if !fixed.is_empty() {
+            return Err(fixed);
+        }
+        apply(&new_config, &changed).map_err(|why| vec![why])?;
+        *current = Arc::new(new_config);
+        Ok(changed)
+    }
+}
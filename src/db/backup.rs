@@ -0,0 +1,293 @@
+use std::{collections::{HashMap, HashSet}, fs, path::Path};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{db::{new_connection, stats, User}, error::{self, Error}, logging::log};
+
+pub enum BackupFormat {
+    Json,
+    Csv,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct OtherId {
+    pub secondary_id: u64,
+    pub main_id: u64,
+}
+
+#[derive(Serialize, Deserialize, PartialEq)]
+pub struct Zitat {
+    pub id: u64,
+    pub text: String,
+    pub time: String,
+    pub writer: u64,
+    pub deleted_at: Option<String>,
+    pub restored_at: Option<String>,
+    pub guild: Option<u64>,
+    pub channel: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Attribution {
+    pub zitat: u64,
+    pub user: u64,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Backup {
+    pub users: Vec<User>,
+    pub other_ids: Vec<OtherId>,
+    pub zitate: Vec<Zitat>,
+    pub said: Vec<Attribution>,
+    pub assisted: Vec<Attribution>,
+}
+
+const CSV_FILES: [&str; 5] = ["users", "other_ids", "zitate", "said", "assisted"];
+
+fn read_backup_from_db() -> error::Result<Backup> {
+    let connection = new_connection()?;
+    let mut backup = Backup::default();
+
+    let statement = connection.prepare("SELECT id, name FROM users ORDER BY id")?;
+    for row in statement.into_iter() {
+        let row = row?;
+        backup.users.push(User::new(row.read::<i64, _>("id") as u64, row.read::<&str, _>("name").to_string()));
+    }
+
+    let statement = connection.prepare("SELECT secondary_id, main_id FROM other_ids ORDER BY secondary_id")?;
+    for row in statement.into_iter() {
+        let row = row?;
+        backup.other_ids.push(OtherId {
+            secondary_id: row.read::<i64, _>("secondary_id") as u64,
+            main_id: row.read::<i64, _>("main_id") as u64,
+        });
+    }
+
+    let statement = connection.prepare("SELECT id, text, time, writer, deleted_at, restored_at, guild, channel FROM zitate ORDER BY id")?;
+    for row in statement.into_iter() {
+        let row = row?;
+        backup.zitate.push(Zitat {
+            id: row.read::<i64, _>("id") as u64,
+            text: row.read::<&str, _>("text").to_string(),
+            time: row.read::<&str, _>("time").to_string(),
+            writer: row.read::<i64, _>("writer") as u64,
+            deleted_at: row.read::<Option<&str>, _>("deleted_at").map(String::from),
+            restored_at: row.read::<Option<&str>, _>("restored_at").map(String::from),
+            guild: row.read::<Option<i64>, _>("guild").map(|id| id as u64),
+            channel: row.read::<Option<i64>, _>("channel").map(|id| id as u64),
+        });
+    }
+
+    for (table, target) in [("said", &mut backup.said), ("assisted", &mut backup.assisted)] {
+        let statement = connection.prepare(format!("SELECT zitat, user FROM {table} ORDER BY zitat, user"))?;
+        for row in statement.into_iter() {
+            let row = row?;
+            target.push(Attribution {
+                zitat: row.read::<i64, _>("zitat") as u64,
+                user: row.read::<i64, _>("user") as u64,
+            });
+        }
+    }
+    Ok(backup)
+}
+
+fn write_csv<T: Serialize>(path: &Path, rows: &[T]) -> error::Result<()> {
+    let mut writer = csv::Writer::from_path(path).map_err(|why| Error::File(format!("Could not create {}: {why}", path.display())))?;
+    for row in rows {
+        writer.serialize(row).map_err(|why| Error::File(format!("Could not write {}: {why}", path.display())))?;
+    }
+    writer.flush().map_err(|why| Error::File(format!("Could not write {}: {why}", path.display())))
+}
+
+fn read_csv<T: DeserializeOwned>(path: &Path) -> error::Result<Vec<T>> {
+    let mut reader = csv::Reader::from_path(path).map_err(|why| Error::File(format!("Could not open {}: {why}", path.display())))?;
+    reader
+        .deserialize()
+        .collect::<Result<Vec<T>, _>>()
+        .map_err(|why| Error::File(format!("Could not parse {}: {why}", path.display())))
+}
+
+pub fn export(path: &str, format: BackupFormat) -> error::Result<String> {
+    let backup = read_backup_from_db()?;
+    match format {
+        BackupFormat::Json => {
+            let json = serde_json::to_string_pretty(&backup).map_err(|why| Error::File(format!("Could not serialize the backup: {why}")))?;
+            fs::write(path, json).map_err(|why| Error::File(format!("Could not write {path}: {why}")))?;
+        }
+        BackupFormat::Csv => {
+            fs::create_dir_all(path).map_err(|why| Error::File(format!("Could not create directory {path}: {why}")))?;
+            let dir = Path::new(path);
+            write_csv(&dir.join("users.csv"), &backup.users)?;
+            write_csv(&dir.join("other_ids.csv"), &backup.other_ids)?;
+            write_csv(&dir.join("zitate.csv"), &backup.zitate)?;
+            write_csv(&dir.join("said.csv"), &backup.said)?;
+            write_csv(&dir.join("assisted.csv"), &backup.assisted)?;
+        }
+    }
+    log(&format!("Exported DB to {path}"), "INFO");
+    Ok(format!(
+        "Exported {} users, {} other IDs, {} Zitate, {} said and {} assisted to {path}",
+        backup.users.len(),
+        backup.other_ids.len(),
+        backup.zitate.len(),
+        backup.said.len(),
+        backup.assisted.len()
+    ))
+}
+
+fn read_backup_from_file(path: &str, format: BackupFormat) -> error::Result<Backup> {
+    match format {
+        BackupFormat::Json => {
+            let json = fs::read_to_string(path).map_err(|why| Error::File(format!("Could not read {path}: {why}")))?;
+            serde_json::from_str(&json).map_err(|why| Error::File(format!("Could not parse {path}: {why}")))
+        }
+        BackupFormat::Csv => {
+            let dir = Path::new(path);
+            if let Some(missing) = CSV_FILES.iter().find(|file| !dir.join(format!("{file}.csv")).exists()) {
+                return Err(Error::File(format!("{missing}.csv not found in {path}")));
+            }
+            Ok(Backup {
+                users: read_csv(&dir.join("users.csv"))?,
+                other_ids: read_csv(&dir.join("other_ids.csv"))?,
+                zitate: read_csv(&dir.join("zitate.csv"))?,
+                said: read_csv(&dir.join("said.csv"))?,
+                assisted: read_csv(&dir.join("assisted.csv"))?,
+            })
+        }
+    }
+}
+
+pub fn import(path: &str, format: BackupFormat, dry_run: bool) -> error::Result<String> {
+    let backup = read_backup_from_file(path, format)?;
+    let existing = read_backup_from_db()?;
+
+    let existing_users: HashMap<u64, &str> = existing.users.iter().map(|u| (u.id, u.name.as_str())).collect();
+    let existing_names: HashMap<&str, u64> = existing.users.iter().map(|u| (u.name.as_str(), u.id)).collect();
+    let existing_other_ids: HashMap<u64, u64> = existing.other_ids.iter().map(|o| (o.secondary_id, o.main_id)).collect();
+    let existing_zitate: HashMap<u64, &Zitat> = existing.zitate.iter().map(|z| (z.id, z)).collect();
+    let existing_said: HashSet<(u64, u64)> = existing.said.iter().map(|a| (a.zitat, a.user)).collect();
+    let existing_assisted: HashSet<(u64, u64)> = existing.assisted.iter().map(|a| (a.zitat, a.user)).collect();
+
+    let mut conflicts = Vec::new();
+    let mut new_users = Vec::new();
+    for user in &backup.users {
+        match (existing_users.get(&user.id), existing_names.get(user.name.as_str())) {
+            (Some(name), _) if *name == user.name => (),
+            (Some(name), _) => conflicts.push(format!("User {} is called {name} in the DB but {} in the import", user.id, user.name)),
+            (None, Some(id)) => conflicts.push(format!("Name {} belongs to user {id} in the DB but to {} in the import", user.name, user.id)),
+            (None, None) => new_users.push(user),
+        }
+    }
+    let user_ids: HashSet<u64> = existing_users.keys().copied().chain(backup.users.iter().map(|u| u.id)).collect();
+
+    let mut new_other_ids = Vec::new();
+    for other_id in &backup.other_ids {
+        if !user_ids.contains(&other_id.main_id) {
+            conflicts.push(format!("Other ID {} points to unknown user {}", other_id.secondary_id, other_id.main_id));
+            continue;
+        }
+        match existing_other_ids.get(&other_id.secondary_id) {
+            Some(main_id) if *main_id == other_id.main_id => (),
+            Some(main_id) => conflicts.push(format!("Other ID {} belongs to user {main_id} in the DB but to {} in the import", other_id.secondary_id, other_id.main_id)),
+            None => new_other_ids.push(other_id),
+        }
+    }
+
+    let mut new_zitate = Vec::new();
+    for zitat in &backup.zitate {
+        if !user_ids.contains(&zitat.writer) {
+            conflicts.push(format!("Zitat {} was written by unknown user {}", zitat.id, zitat.writer));
+            continue;
+        }
+        match existing_zitate.get(&zitat.id) {
+            Some(existing_zitat) if *existing_zitat == zitat => (),
+            Some(_) => conflicts.push(format!("Zitat {} differs between the DB and the import", zitat.id)),
+            None => new_zitate.push(zitat),
+        }
+    }
+    let zitat_ids: HashSet<u64> = existing_zitate.keys().copied().chain(backup.zitate.iter().map(|z| z.id)).collect();
+
+    let mut new_attributions = Vec::new();
+    for (table, attributions, existing_attributions) in [("said", &backup.said, &existing_said), ("assisted", &backup.assisted, &existing_assisted)] {
+        for attribution in attributions {
+            if !zitat_ids.contains(&attribution.zitat) {
+                conflicts.push(format!("{table} references unknown Zitat {}", attribution.zitat));
+            } else if !user_ids.contains(&attribution.user) {
+                conflicts.push(format!("{table} references unknown user {}", attribution.user));
+            } else if !existing_attributions.contains(&(attribution.zitat, attribution.user)) {
+                new_attributions.push((table, attribution));
+            }
+        }
+    }
+
+    let mut report = format!(
+        "Import of {path}:\nNew users: {}\nNew other IDs: {}\nNew Zitate: {}\nNew said/assisted: {}\nConflicts: {}",
+        new_users.len(),
+        new_other_ids.len(),
+        new_zitate.len(),
+        new_attributions.len(),
+        conflicts.len()
+    );
+    for conflict in &conflicts {
+        report.push_str(&format!("\n - {conflict}"));
+    }
+    if dry_run {
+        return Ok(format!("{report}\nDry run, nothing was imported"));
+    }
+    if !conflicts.is_empty() {
+        return Ok(format!("{report}\nImport aborted because of conflicts"));
+    }
+
+    write_backup_to_db(&new_users, &new_other_ids, &new_zitate, &new_attributions)?;
+    stats::invalidate();
+    log(&format!("Imported {path} into DB"), "INFO");
+    Ok(format!("{report}\nImport successful"))
+}
+
+// Dropping the connection on an error rolls back the open transaction
+fn write_backup_to_db(
+    users: &[&User],
+    other_ids: &[&OtherId],
+    zitate: &[&Zitat],
+    attributions: &[(&str, &Attribution)],
+) -> error::Result<()> {
+    let connection = new_connection()?;
+    connection.execute("BEGIN TRANSACTION")?;
+    for user in users {
+        let mut statement = connection.prepare("INSERT INTO users(id, name) VALUES(:id, :name)")?;
+        statement.bind((":id", user.id as i64))?;
+        statement.bind((":name", user.name.as_str()))?;
+        statement.next()?;
+    }
+    for other_id in other_ids {
+        let mut statement = connection.prepare("INSERT INTO other_ids(secondary_id, main_id) VALUES(:secondary_id, :main_id)")?;
+        statement.bind((":secondary_id", other_id.secondary_id as i64))?;
+        statement.bind((":main_id", other_id.main_id as i64))?;
+        statement.next()?;
+    }
+    for zitat in zitate {
+        let mut statement = connection.prepare("INSERT INTO zitate(id, text, time, writer, deleted_at, restored_at, guild, channel) VALUES(:id, :text, :time, :writer, :deleted_at, :restored_at, :guild, :channel)")?;
+        statement.bind((":id", zitat.id as i64))?;
+        statement.bind((":text", zitat.text.as_str()))?;
+        statement.bind((":time", zitat.time.as_str()))?;
+        statement.bind((":writer", zitat.writer as i64))?;
+        statement.bind((":deleted_at", zitat.deleted_at.as_deref()))?;
+        statement.bind((":restored_at", zitat.restored_at.as_deref()))?;
+        statement.bind((":guild", zitat.guild.map(|id| id as i64)))?;
+        statement.bind((":channel", zitat.channel.map(|id| id as i64)))?;
+        statement.next()?;
+
+        let mut statement = connection.prepare("INSERT INTO zitate_fts(rowid, text) VALUES(:id, :text)")?;
+        statement.bind((":id", zitat.id as i64))?;
+        statement.bind((":text", zitat.text.as_str()))?;
+        statement.next()?;
+    }
+    for (table, attribution) in attributions {
+        let mut statement = connection.prepare(format!("INSERT INTO {table}(zitat, user) VALUES(:zitat, :user)"))?;
+        statement.bind((":zitat", attribution.zitat as i64))?;
+        statement.bind((":user", attribution.user as i64))?;
+        statement.next()?;
+    }
+    connection.execute("COMMIT")?;
+    Ok(())
+}
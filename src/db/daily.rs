@@ -0,0 +1,113 @@
+use chrono::{Datelike, NaiveDate};
+
+use crate::{db::{format_zitat, new_connection}, error::Result, logging::log};
+
+pub fn is_posted(guild: u64, date: &str) -> Result<bool> {
+    let connection = new_connection()?;
+    let mut statement = connection.prepare("SELECT d.zitat FROM daily_zitate AS d
+        JOIN zitate AS z ON z.id = d.zitat
+        WHERE d.date = :date AND z.guild = :guild")?;
+    statement.bind((":date", date))?;
+    statement.bind((":guild", guild as i64))?;
+    Ok(statement.next()? == sqlite::State::Row)
+}
+
+// Returns the picked Zitat and its cycle, it only counts as posted after mark_posted
+pub fn pick_zitat(guild: u64) -> Result<Option<(u64, i64)>> {
+    let connection = new_connection()?;
+
+    let mut statement = connection.prepare("SELECT coalesce(max(d.cycle), 0) AS cycle FROM daily_zitate AS d
+        JOIN zitate AS z ON z.id = d.zitat
+        WHERE z.guild = :guild")?;
+    statement.bind((":guild", guild as i64))?;
+    statement.next()?;
+    let mut cycle = statement.read::<i64, _>("cycle")?;
+
+    let query = "
+        SELECT z.id AS id
+        FROM active_zitate AS z
+        WHERE z.guild = :guild AND z.id NOT IN (SELECT zitat FROM daily_zitate WHERE cycle = :cycle)
+        ORDER BY RANDOM()
+        LIMIT 1
+    ";
+    let mut statement = connection.prepare(query)?;
+    statement.bind((":guild", guild as i64))?;
+    statement.bind((":cycle", cycle))?;
+    if let sqlite::State::Done = statement.next()? {
+        cycle += 1;
+        log(&format!("All Zitate of guild {guild} have been Zitat des Tages, starting cycle {cycle}"), "INFO");
+        statement = connection.prepare(query)?;
+        statement.bind((":guild", guild as i64))?;
+        statement.bind((":cycle", cycle))?;
+        if let sqlite::State::Done = statement.next()? {
+            return Ok(None);
+        }
+    }
+    Ok(Some((statement.read::<i64, _>("id")? as u64, cycle)))
+}
+
+pub fn mark_posted(date: &str, zitat: u64, cycle: i64) -> Result<()> {
+    let connection = new_connection()?;
+    let mut statement = connection.prepare("INSERT INTO daily_zitate(date, zitat, cycle) VALUES(:date, :zitat, :cycle)")?;
+    statement.bind((":date", date))?;
+    statement.bind((":zitat", zitat as i64))?;
+    statement.bind((":cycle", cycle))?;
+    statement.next()?;
+    log(&format!("Posted Zitat with ID {zitat} as Zitat des Tages for {date}"), "INFO");
+    Ok(())
+}
+
+pub fn is_anniversary_posted(guild: u64, date: &str) -> Result<bool> {
+    let connection = new_connection()?;
+    let mut statement = connection.prepare("SELECT 1 FROM anniversary_posts WHERE guild = :guild AND date = :date")?;
+    statement.bind((":guild", guild as i64))?;
+    statement.bind((":date", date))?;
+    Ok(statement.next()? == sqlite::State::Row)
+}
+
+pub fn mark_anniversary_posted(guild: u64, date: &str) -> Result<()> {
+    let connection = new_connection()?;
+    let mut statement = connection.prepare("INSERT OR IGNORE INTO anniversary_posts(guild, date) VALUES(:guild, :date)")?;
+    statement.bind((":guild", guild as i64))?;
+    statement.bind((":date", date))?;
+    statement.next()?;
+    Ok(())
+}
+
+pub fn get_anniversary_zitate(guild: u64, date: NaiveDate) -> Result<Option<String>> {
+    let connection = new_connection()?;
+    let mut statement = connection.prepare("
+        SELECT z.id AS id, z.text AS text, z.guild AS guild, z.channel AS channel, strftime('%Y', z.time, 'localtime') AS year, group_concat(u.name, ', ') AS sayers
+        FROM active_zitate AS z
+        LEFT JOIN said AS s ON z.id = s.zitat
+        LEFT JOIN users AS u ON s.user = u.id
+        WHERE z.guild = :guild AND strftime('%m-%d', z.time, 'localtime') = :day AND strftime('%Y', z.time, 'localtime') < :year
+        GROUP BY z.id
+        ORDER BY year DESC, z.id
+    ")?;
+    statement.bind((":guild", guild as i64))?;
+    statement.bind((":day", date.format("%m-%d").to_string().as_str()))?;
+    statement.bind((":year", date.year().to_string().as_str()))?;
+
+    let mut current_year = String::new();
+    let zitate: Vec<String> = statement.into_iter().map(|row| {
+        let row = row?;
+        let year = row.read::<&str, _>("year");
+        let sayers = row.read::<Option<&str>, _>("sayers").unwrap_or("unbekannt");
+        let zitat = format!(
+            "{}\nGesagt von: {sayers}",
+            format_zitat(&row)
+        );
+        if year == current_year {
+            Ok(zitat)
+        } else {
+            current_year = year.to_string();
+            Ok(format!("__{year}__\n{zitat}"))
+        }
+    }).collect::<Result<_>>()?;
+    if zitate.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(format!("An diesem Tag ({}):\n\n{}", date.format("%d.%m."), zitate.join("\n------------------\n"))))
+    }
+}
@@ -0,0 +1,129 @@
+use crate::{db::{new_connection, stats}, error::{Error, Result}, logging::log};
+
+pub struct Guild {
+    pub id: u64,
+    pub channel_zitate: u64,
+    pub channel_bot: u64,
+    pub channel_daily: Option<u64>,
+}
+
+fn query(condition: &str, value: Option<u64>) -> Result<Vec<Guild>> {
+    let connection = new_connection()?;
+    let mut statement = connection.prepare(format!("SELECT id, channel_zitate, channel_bot, channel_daily
+        FROM guilds
+        WHERE {condition}
+        ORDER BY id"))?;
+    if let Some(value) = value {
+        statement.bind((":value", value as i64))?;
+    }
+    statement
+        .into_iter()
+        .map(|row| {
+            let row = row?;
+            Ok(Guild {
+                id: row.read::<i64, _>("id") as u64,
+                channel_zitate: row.read::<i64, _>("channel_zitate") as u64,
+                channel_bot: row.read::<i64, _>("channel_bot") as u64,
+                channel_daily: row.read::<Option<i64>, _>("channel_daily").map(|id| id as u64),
+            })
+        })
+        .collect()
+}
+
+pub fn get(id: u64) -> Result<Option<Guild>> {
+    Ok(query("id = :value", Some(id))?.pop())
+}
+
+pub fn get_by_zitate_channel(channel_id: u64) -> Result<Option<Guild>> {
+    Ok(query("channel_zitate = :value", Some(channel_id))?.pop())
+}
+
+pub fn get_for_zitat(zitat: u64) -> Result<Option<Guild>> {
+    Ok(query("id = (SELECT guild FROM zitate WHERE id = :value)", Some(zitat))?.pop())
+}
+
+pub fn get_all() -> Result<Vec<Guild>> {
+    query("1", None)
+}
+
+pub fn set(guild: &Guild) -> Result<String> {
+    let connection = new_connection()?;
+    let mut statement = connection.prepare("INSERT INTO guilds(id, channel_zitate, channel_bot, channel_daily)
+        VALUES(:id, :zitate, :bot, :daily)
+        ON CONFLICT(id) DO UPDATE SET channel_zitate = :zitate, channel_bot = :bot, channel_daily = :daily")?;
+    statement.bind((":id", guild.id as i64))?;
+    statement.bind((":zitate", guild.channel_zitate as i64))?;
+    statement.bind((":bot", guild.channel_bot as i64))?;
+    statement.bind((":daily", guild.channel_daily.map(|id| id as i64)))?;
+    match statement.next() {
+        Ok(_) => {
+            log(&format!("Saved channels for guild {}", guild.id), "INFO");
+            Ok(String::from("Guild saved"))
+        }
+        Err(why) => {
+            log(&format!("Could not save guild {}: {why}", guild.id), "ERR ");
+            Err(Error::UserInput(String::from("Could not save guild (is the Zitate channel already used by another guild?)")))
+        }
+    }
+}
+
+pub fn remove(id: u64) -> Result<String> {
+    let connection = new_connection()?;
+    let mut statement = connection.prepare("SELECT count(*) FROM zitate WHERE guild = :id")?;
+    statement.bind((":id", id as i64))?;
+    statement.next()?;
+    if statement.read::<i64, _>(0)? > 0 {
+        return Ok(String::from("Guild still has Zitate and cannot be removed"));
+    }
+    drop(statement);
+    let mut statement = connection.prepare("DELETE FROM guilds WHERE id = :id")?;
+    statement.bind((":id", id as i64))?;
+    statement.next()?;
+    drop(statement);
+    if connection.change_count() == 0 {
+        Ok(String::from("Guild not found"))
+    } else {
+        log(&format!("Removed guild {id}"), "INFO");
+        Ok(String::from("Guild removed"))
+    }
+}
+
+pub fn list() -> Result<String> {
+    let guilds: Vec<String> = get_all()?
+        .iter()
+        .map(|guild| format!(
+            "{}: Zitate {}, Bot {}, Zitat des Tages {}",
+            guild.id,
+            guild.channel_zitate,
+            guild.channel_bot,
+            guild.channel_daily.map(|id| id.to_string()).unwrap_or_else(|| String::from("-"))
+        ))
+        .collect();
+    if guilds.is_empty() {
+        Ok(String::from("No guilds configured"))
+    } else {
+        Ok(guilds.join("\n"))
+    }
+}
+
+pub fn adopt_unscoped(guild: &Guild) -> Result<()> {
+    let connection = new_connection()?;
+    let mut statement = connection.prepare("UPDATE zitate SET guild = :id WHERE guild IS NULL")?;
+    statement.bind((":id", guild.id as i64))?;
+    statement.next()?;
+    drop(statement);
+    let adopted = connection.change_count();
+    let mut statement = connection.prepare("UPDATE zitate SET channel = :channel WHERE guild = :id AND channel IS NULL")?;
+    statement.bind((":id", guild.id as i64))?;
+    statement.bind((":channel", guild.channel_zitate as i64))?;
+    statement.next()?;
+    drop(statement);
+    let mut statement = connection.prepare("UPDATE anniversary_posts SET guild = :id WHERE guild IS NULL")?;
+    statement.bind((":id", guild.id as i64))?;
+    statement.next()?;
+    if adopted > 0 {
+        stats::invalidate();
+        log(&format!("Assigned {adopted} Zitate without guild to guild {}", guild.id), "INFO");
+    }
+    Ok(())
+}
@@ -0,0 +1,166 @@
+use sqlite::Connection;
+
+use crate::{error::{Error, Result}, logging::log};
+
+// Entry n is schema version n+1 (stored in PRAGMA user_version); only ever append to this list
+const MIGRATIONS: &[(&str, &str)] = &[
+    ("Initial schema", "
+        CREATE TABLE IF NOT EXISTS users(
+            id INTEGER PRIMARY KEY,
+            name TEXT UNIQUE NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS other_ids(
+            secondary_id INTEGER PRIMARY KEY,
+            main_id INTEGER NOT NULL,
+            FOREIGN KEY(main_id) REFERENCES users(id) ON DELETE RESTRICT
+        );
+        CREATE TABLE IF NOT EXISTS zitate(
+            id INTEGER PRIMARY KEY,
+            text TEXT NOT NULL,
+            time STRING NOT NULL,
+            writer INTEGER NOT NULL,
+            FOREIGN KEY(writer) REFERENCES users(id)
+        );
+        CREATE TABLE IF NOT EXISTS said(
+            zitat INTEGER,
+            user INTEGER,
+            PRIMARY KEY(zitat, user),
+            FOREIGN KEY(zitat) REFERENCES zitate(id) ON DELETE CASCADE,
+            FOREIGN KEY(user) REFERENCES users(id)
+        );
+        CREATE TABLE IF NOT EXISTS assisted(
+            zitat INTEGER,
+            user INTEGER,
+            PRIMARY KEY(zitat, user),
+            FOREIGN KEY(zitat) REFERENCES zitate(id) ON DELETE CASCADE,
+            FOREIGN KEY(user) REFERENCES users(id)
+        );
+    "),
+    ("Full-text search index for Zitate", "
+        CREATE VIRTUAL TABLE zitate_fts USING fts5(text);
+        INSERT INTO zitate_fts(rowid, text) SELECT id, text FROM zitate;
+    "),
+    ("Zitat des Tages", "
+        CREATE TABLE daily_zitate(
+            date TEXT PRIMARY KEY,
+            zitat INTEGER NOT NULL,
+            cycle INTEGER NOT NULL,
+            FOREIGN KEY(zitat) REFERENCES zitate(id) ON DELETE CASCADE
+        );
+    "),
+    ("An diesem Tag", "
+        CREATE TABLE anniversary_posts(
+            date TEXT PRIMARY KEY
+        );
+    "),
+    ("Edit history for Zitate", "
+        CREATE TABLE zitat_edits(
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            zitat INTEGER NOT NULL,
+            text TEXT NOT NULL,
+            replaced_at TEXT NOT NULL,
+            FOREIGN KEY(zitat) REFERENCES zitate(id) ON DELETE CASCADE
+        );
+    "),
+    ("Soft delete for Zitate", "
+        ALTER TABLE zitate ADD COLUMN deleted_at TEXT;
+        CREATE VIEW active_zitate AS SELECT * FROM zitate WHERE deleted_at IS NULL;
+    "),
+    ("Nickname aliases for users", "
+        CREATE TABLE name_aliases(
+            alias TEXT PRIMARY KEY COLLATE NOCASE,
+            user INTEGER NOT NULL,
+            FOREIGN KEY(user) REFERENCES users(id) ON DELETE CASCADE
+        );
+    "),
+    ("QA threads", "
+        CREATE TABLE qa_threads(
+            zitat INTEGER PRIMARY KEY,
+            thread INTEGER NOT NULL,
+            status_message INTEGER,
+            FOREIGN KEY(zitat) REFERENCES zitate(id) ON DELETE CASCADE
+        );
+    "),
+    ("Reminders for unattributed Zitate", "
+        CREATE TABLE qa_reminders(
+            zitat INTEGER PRIMARY KEY,
+            writer_reminded_at TEXT,
+            owner_notified_at TEXT,
+            FOREIGN KEY(zitat) REFERENCES zitate(id) ON DELETE CASCADE
+        );
+    "),
+    ("Multiple guilds", "
+        CREATE TABLE guilds(
+            id INTEGER PRIMARY KEY,
+            channel_zitate INTEGER UNIQUE NOT NULL,
+            channel_bot INTEGER NOT NULL,
+            channel_daily INTEGER
+        );
+        ALTER TABLE zitate ADD COLUMN guild INTEGER;
+        CREATE INDEX zitate_guild ON zitate(guild);
+        DROP VIEW active_zitate;
+        CREATE VIEW active_zitate AS SELECT * FROM zitate WHERE deleted_at IS NULL;
+        CREATE TABLE daily_zitate_new(
+            date TEXT NOT NULL,
+            zitat INTEGER NOT NULL,
+            cycle INTEGER NOT NULL,
+            PRIMARY KEY(date, zitat),
+            FOREIGN KEY(zitat) REFERENCES zitate(id) ON DELETE CASCADE
+        );
+        INSERT INTO daily_zitate_new(date, zitat, cycle) SELECT date, zitat, cycle FROM daily_zitate;
+        DROP TABLE daily_zitate;
+        ALTER TABLE daily_zitate_new RENAME TO daily_zitate;
+        CREATE TABLE anniversary_posts_new(
+            guild INTEGER,
+            date TEXT NOT NULL,
+            PRIMARY KEY(guild, date)
+        );
+        INSERT INTO anniversary_posts_new(date) SELECT date FROM anniversary_posts;
+        DROP TABLE anniversary_posts;
+        ALTER TABLE anniversary_posts_new RENAME TO anniversary_posts;
+    "),
+    // Only fills the channel of Zitate whose guild is already known, the guilds table is still empty when
+    // upgrading from before version 10; guild::adopt_unscoped fills in the rest once the default guild is saved
+    ("Channel of each Zitat", "
+        ALTER TABLE zitate ADD COLUMN channel INTEGER;
+        UPDATE zitate SET channel = (SELECT channel_zitate FROM guilds WHERE guilds.id = zitate.guild);
+        DROP VIEW active_zitate;
+        CREATE VIEW active_zitate AS SELECT * FROM zitate WHERE deleted_at IS NULL;
+    "),
+    ("Restored Zitate", "
+        ALTER TABLE zitate ADD COLUMN restored_at TEXT;
+        DROP VIEW active_zitate;
+        CREATE VIEW active_zitate AS SELECT * FROM zitate WHERE deleted_at IS NULL;
+    "),
+];
+
+fn get_version(connection: &Connection) -> Result<usize> {
+    let mut statement = connection.prepare("PRAGMA user_version")?;
+    statement.next()?;
+    Ok(statement.read::<i64, _>(0)? as usize)
+}
+
+pub fn run(connection: &Connection) -> Result<()> {
+    let current_version = get_version(connection)?;
+    let latest_version = MIGRATIONS.len();
+    if current_version > latest_version {
+        return Err(Error::Config(format!("DB has schema version {current_version}, but this binary only knows up to version {latest_version}")));
+    }
+    if current_version == latest_version {
+        log(&format!("DB schema is up to date (version {current_version})"), "INFO");
+        return Ok(());
+    }
+
+    for (i, (description, sql)) in MIGRATIONS.iter().enumerate().skip(current_version) {
+        let version = i + 1;
+        log(&format!("Applying DB migration {version}: {description}"), "INFO");
+        let result = connection.execute(format!("BEGIN TRANSACTION;\n{sql}\nPRAGMA user_version = {version};\nCOMMIT;"));
+        if let Err(why) = result {
+            let _ = connection.execute("ROLLBACK");
+            log(&format!("DB migration {version} failed"), "ERR ");
+            return Err(why.into());
+        }
+    }
+    log(&format!("DB schema migrated from version {current_version} to {latest_version}"), "INFO");
+    Ok(())
+}
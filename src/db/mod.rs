@@ -1,196 +1,572 @@
 use std::sync::RwLock;
 
-use pml::PmlStruct;
+use chrono::{DateTime, Local, Months, NaiveDate, Utc};
 use serenity::model::{prelude::Message, id::UserId as SerenityUserId};
 
-use crate::{logging::log, QAType, RankingType, OVERALL_ZITATE_COUNT};
+use crate::{config::Config, error::{Error, Result}, logging::log, QAType, RankingType};
 
+pub mod backup;
+pub mod daily;
+pub mod guild;
+mod migrations;
+pub mod qa_thread;
+pub mod stats;
 pub mod user;
 pub use user::User;
 
 static DB_FILE: RwLock<String> = RwLock::new(String::new());
 
-pub fn new_connection() -> sqlite::Connection {
-    sqlite::open(&*DB_FILE.read().unwrap()).expect("Failed to open DB")
+#[derive(Default)]
+pub struct TimeWindow {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
 }
 
-pub fn add_qa(r#type: QAType, user: User, id: u64) -> String {
-    let connection = new_connection();
+impl TimeWindow {
+    pub fn year(year: i32) -> Option<Self> {
+        Some(Self {
+            from: Some(NaiveDate::from_ymd_opt(year, 1, 1)?),
+            to: Some(NaiveDate::from_ymd_opt(year, 12, 31)?),
+        })
+    }
 
-    let mut statement = connection.prepare("SELECT * FROM said WHERE zitat=:zitat AND user=:user").unwrap();
-    statement.bind((":zitat", id as i64)).unwrap();
-    statement.bind((":user", user.id as i64)).unwrap();
-    let already_said = statement.into_iter().next().is_some();
+    pub fn month(year: i32, month: u32) -> Option<Self> {
+        let from = NaiveDate::from_ymd_opt(year, month, 1)?;
+        Some(Self {
+            from: Some(from),
+            to: Some(from.checked_add_months(Months::new(1))?.pred_opt()?),
+        })
+    }
 
-    let mut statement = connection.prepare("SELECT * FROM assisted WHERE zitat=:zitat AND user=:user").unwrap();
-    statement.bind((":zitat", id as i64)).unwrap();
-    statement.bind((":user", user.id as i64)).unwrap();
-    let already_assisted = statement.into_iter().next().is_some();
+    pub fn describe(&self) -> String {
+        match (self.from, self.to) {
+            (None, None) => String::new(),
+            (Some(from), None) => format!(" ab {}", from.format("%d.%m.%Y")),
+            (None, Some(to)) => format!(" bis {}", to.format("%d.%m.%Y")),
+            (Some(from), Some(to)) => format!(" vom {} bis {}", from.format("%d.%m.%Y"), to.format("%d.%m.%Y")),
+        }
+    }
 
-    if already_said && r#type == QAType::Said || already_assisted && r#type == QAType::Assisted {
-        return String::from("Der ist dafür bereits eingetragen.");
+    pub fn parse_date(input: &str) -> Option<NaiveDate> {
+        NaiveDate::parse_from_str(input.trim(), "%d.%m.%Y").ok()
     }
-    if already_said && r#type == QAType::Assisted {
-        return String::from("Der hat das Zitat schon gesagt.");
+
+    fn sql_condition(&self, column: &str) -> String {
+        let mut conditions = vec![String::from("1")];
+        if let Some(from) = self.from {
+            conditions.push(format!("date({column}, 'localtime') >= '{}'", from.format("%Y-%m-%d")));
+        }
+        if let Some(to) = self.to {
+            conditions.push(format!("date({column}, 'localtime') <= '{}'", to.format("%Y-%m-%d")));
+        }
+        conditions.join(" AND ")
     }
-    if already_assisted && r#type == QAType::Said {
-        return String::from("Der hat schon einen Assist für das Zitat.");
+}
+
+#[derive(Default)]
+pub struct ZitatFilter {
+    pub guild: u64,
+    pub said: Option<u64>,
+    pub writer: Option<u64>,
+    pub window: TimeWindow,
+}
+
+impl ZitatFilter {
+    fn sql_condition(&self) -> String {
+        let mut conditions = vec![String::from("z.guild = :guild"), self.window.sql_condition("z.time")];
+        if self.said.is_some() {
+            conditions.push(String::from("EXISTS (SELECT 1 FROM said AS s WHERE s.zitat = z.id AND s.user = :said)"));
+        }
+        if self.writer.is_some() {
+            conditions.push(String::from("z.writer = :writer"));
+        }
+        conditions.join(" AND ")
     }
 
+    fn bind(&self, statement: &mut sqlite::Statement) -> Result<()> {
+        statement.bind((":guild", self.guild as i64))?;
+        if let Some(said) = self.said {
+            statement.bind((":said", said as i64))?;
+        }
+        if let Some(writer) = self.writer {
+            statement.bind((":writer", writer as i64))?;
+        }
+        Ok(())
+    }
+}
+
+pub fn new_connection() -> Result<sqlite::Connection> {
+    let mut connection = sqlite::open(&*DB_FILE.read().unwrap())?;
+    connection.set_busy_timeout(5000)?;
+    Ok(connection)
+}
+
+pub fn add_qa(r#type: QAType, users: Vec<Result<User, String>>, id: u64) -> Result<String> {
+    let connection = new_connection()?;
     let table_name = match r#type {
         QAType::Said => "said",
         QAType::Assisted => "assisted",
     };
-    let mut statement = connection.prepare(format!("INSERT INTO {table_name}(zitat, user) VALUES(:zitat, :user)")).unwrap();
-    let _ = statement.bind((":zitat", id as i64));
-    let _ = statement.bind((":user", user.id as i64));
-    let _ = statement.next();
 
-    log(&format!("Added {} to {table_name} of Zitat with ID {id} in DB", user.name), "INFO");
-    format!("{} erfolgreich hinzugefügt.", user.name)
+    connection.execute("BEGIN TRANSACTION")?;
+    let results: Vec<String> = users.into_iter().map(|user| {
+        let user = match user {
+            Ok(user) => user,
+            Err(why) => return Ok(why),
+        };
+
+        let mut statement = connection.prepare("SELECT * FROM said WHERE zitat=:zitat AND user=:user")?;
+        statement.bind((":zitat", id as i64))?;
+        statement.bind((":user", user.id as i64))?;
+        let already_said = statement.into_iter().next().is_some();
+
+        let mut statement = connection.prepare("SELECT * FROM assisted WHERE zitat=:zitat AND user=:user")?;
+        statement.bind((":zitat", id as i64))?;
+        statement.bind((":user", user.id as i64))?;
+        let already_assisted = statement.into_iter().next().is_some();
+
+        if already_said && r#type == QAType::Said || already_assisted && r#type == QAType::Assisted {
+            return Ok(format!("{} ist dafür bereits eingetragen.", user.name));
+        }
+        if already_said && r#type == QAType::Assisted {
+            return Ok(format!("{} hat das Zitat schon gesagt.", user.name));
+        }
+        if already_assisted && r#type == QAType::Said {
+            return Ok(format!("{} hat schon einen Assist für das Zitat.", user.name));
+        }
+
+        let mut statement = connection.prepare(format!("INSERT INTO {table_name}(zitat, user) VALUES(:zitat, :user)"))?;
+        statement.bind((":zitat", id as i64))?;
+        statement.bind((":user", user.id as i64))?;
+        statement.next()?;
+
+        log(&format!("Added {} to {table_name} of Zitat with ID {id} in DB", user.name), "INFO");
+        Ok(format!("{} erfolgreich hinzugefügt.", user.name))
+    }).collect::<Result<_>>()?;
+    connection.execute("COMMIT")?;
+
+    Ok(results.join("\n"))
+}
+
+pub fn remove_qa(r#type: QAType, users: Vec<Result<User, String>>, id: u64) -> Result<String> {
+    let connection = new_connection()?;
+    let table_name = match r#type {
+        QAType::Said => "said",
+        QAType::Assisted => "assisted",
+    };
+
+    connection.execute("BEGIN TRANSACTION")?;
+    let results: Vec<String> = users.into_iter().map(|user| {
+        let user = match user {
+            Ok(user) => user,
+            Err(why) => return Ok(why),
+        };
+
+        let mut statement = connection.prepare(format!("DELETE FROM {table_name} WHERE zitat=:zitat AND user=:user"))?;
+        statement.bind((":zitat", id as i64))?;
+        statement.bind((":user", user.id as i64))?;
+        statement.next()?;
+        drop(statement);
+        if connection.change_count() == 0 {
+            return Ok(format!("{} ist dafür nicht eingetragen.", user.name));
+        }
+
+        log(&format!("Removed {} from {table_name} of Zitat with ID {id} in DB", user.name), "INFO");
+        Ok(format!("{} erfolgreich entfernt.", user.name))
+    }).collect::<Result<_>>()?;
+    connection.execute("COMMIT")?;
+
+    Ok(results.join("\n"))
 }
 
-pub fn get_ranking(r#type: RankingType) -> String {
-    let connection = new_connection();
+pub fn get_attributions(id: u64) -> Result<String> {
+    let connection = new_connection()?;
+    let mut lines = Vec::new();
+    for (table_name, label) in [("said", "Gesagt"), ("assisted", "Assistiert")] {
+        let mut statement = connection.prepare(format!("SELECT u.name AS name
+            FROM {table_name} AS t
+            JOIN users AS u ON t.user = u.id
+            WHERE t.zitat = :zitat
+            ORDER BY u.name"))?;
+        statement.bind((":zitat", id as i64))?;
+        let names: Vec<String> = statement
+            .into_iter()
+            .map(|row| Ok(row?.read::<&str, _>("name").to_string()))
+            .collect::<Result<_>>()?;
+        lines.push(format!("{label}: {}", if names.is_empty() { String::from("niemand") } else { names.join(", ") }));
+    }
+    Ok(format!("Aktuell eingetragen:\n{}", lines.join("\n")))
+}
+
+pub fn get_ranking(r#type: RankingType, guild: u64, window: &TimeWindow) -> Result<String> {
+    let connection = new_connection()?;
+    let condition = format!("z.guild = {guild} AND {}", window.sql_condition("z.time"));
 
     let (type_de, statement) = match r#type {
         RankingType::Said => {
-            let statement = connection.prepare(format!("SELECT COUNT(t.user) as count, users.name
+            let statement = connection.prepare(format!("SELECT COUNT(z.id) as count, users.name
                 FROM
                 users
                 LEFT JOIN said AS t
                 ON users.id = t.user
+                LEFT JOIN active_zitate AS z
+                ON z.id = t.zitat AND {condition}
                 GROUP BY users.id
                 HAVING count > 0
                 ORDER BY count DESC
-                ")).unwrap();
+                "))?;
             ("gesprochene", statement)
         },
         RankingType::Wrote => {
-            let statement = connection.prepare(format!("SELECT COUNT(t.writer) as count, users.name
+            let statement = connection.prepare(format!("SELECT COUNT(z.writer) as count, users.name
                 FROM
                 users
-                LEFT JOIN zitate AS t
-                ON users.id = t.writer
+                LEFT JOIN active_zitate AS z
+                ON users.id = z.writer AND {condition}
                 GROUP BY users.id
                 HAVING count > 0
                 ORDER BY count DESC
-                ")).unwrap();
+                "))?;
             ("geschriebene", statement)
         },
         RankingType::Assisted => {
-            let statement = connection.prepare(format!("SELECT COUNT(t.user) as count, users.name
+            let statement = connection.prepare(format!("SELECT COUNT(z.id) as count, users.name
                 FROM
                 users
                 LEFT JOIN assisted AS t
                 ON users.id = t.user
+                LEFT JOIN active_zitate AS z
+                ON z.id = t.zitat AND {condition}
                 GROUP BY users.id
                 HAVING count > 0
                 ORDER BY count DESC
-                ")).unwrap();
+                "))?;
             ("assistierte", statement)
         },
     };
 
-    format!(
-        "Ranking {type_de} Zitate:\n{}",
-        statement.into_iter()
-            .enumerate()
-            .map(|(i, r)| {
-                let row = r.unwrap();
-                format!(
-                    "{:02}.: {}: {} ({}%)",
-                    i + 1,
-                    row.read::<&str, _>("name"),
-                    row.read::<i64, _>("count"),
-                    get_percentage(row.read::<i64, _>("count"))
-                )
-            })
-            .collect::<Vec<String>>()
-            .join("\n")
-    )
-}
-
-pub fn init(config: &PmlStruct) {
-    *DB_FILE.write().unwrap() = config.get::<String>("dbFile").expect("dbFile value not found in config file");
-    let connection = new_connection();
-    let _ = connection.execute("CREATE TABLE IF NOT EXISTS users(
-        id INTEGER PRIMARY KEY,
-        name TEXT UNIQUE NOT NULL
-    )");
-    let _ = connection.execute("CREATE TABLE IF NOT EXISTS other_ids(
-        secondary_id INTEGER PRIMARY KEY,
-        main_id INTEGER NOT NULL,
-        FOREIGN KEY(main_id) REFERENCES users(id) ON DELETE RESTRICT
-    )");
-    let _ = connection.execute("CREATE TABLE IF NOT EXISTS zitate(
-        id INTEGER PRIMARY KEY,
-        text TEXT NOT NULL,
-        time STRING NOT NULL,
-        writer INTEGER NOT NULL,
-        FOREIGN KEY(writer) REFERENCES users(id)
-    )");
-    let _ = connection.execute("CREATE TABLE IF NOT EXISTS said(
-        zitat INTEGER,
-        user INTEGER,
-        PRIMARY KEY(zitat, user),
-        FOREIGN KEY(zitat) REFERENCES zitate(id) ON DELETE CASCADE,
-        FOREIGN KEY(user) REFERENCES users(id)
-    )");
-    let _ = connection.execute("CREATE TABLE IF NOT EXISTS assisted(
-        zitat INTEGER,
-        user INTEGER,
-        PRIMARY KEY(zitat, user),
-        FOREIGN KEY(zitat) REFERENCES zitate(id) ON DELETE CASCADE,
-        FOREIGN KEY(user) REFERENCES users(id)
-    )");
+    let total = stats::count_zitate(guild, window)?;
+    let ranking = statement.into_iter()
+        .enumerate()
+        .map(|(i, r)| {
+            let row = r?;
+            Ok(format!(
+                "{:02}.: {}: {} ({}%)",
+                i + 1,
+                row.read::<&str, _>("name"),
+                row.read::<i64, _>("count"),
+                stats::get_percentage(row.read::<i64, _>("count"), total)
+            ))
+        })
+        .collect::<Result<Vec<String>>>()?;
+
+    Ok(format!(
+        "Ranking {type_de} Zitate{}:\n{}",
+        window.describe(),
+        ranking.join("\n")
+    ))
+}
+
+pub fn init(config: &Config) -> Result<()> {
+    setup(config.db_file.clone())?;
+    let default_guild = set_default_guild(config)?;
+    guild::adopt_unscoped(&default_guild)?;
     log("Set up database", "INFO");
+    Ok(())
 }
 
-fn get_percentage(count: i64) -> f32 {
-    let total;
-    unsafe {
-        total = OVERALL_ZITATE_COUNT;
-    }
-    (count as f32 * 10_000.0 / total as f32).round() / 100.0
+pub fn set_default_guild(config: &Config) -> Result<guild::Guild> {
+    let default_guild = guild::Guild {
+        id: config.guild_id,
+        channel_zitate: config.channel_zitate,
+        channel_bot: config.channel_bot,
+        channel_daily: config.channel_daily,
+    };
+    guild::set(&default_guild)?;
+    Ok(default_guild)
+}
+
+fn setup(db_file: String) -> Result<()> {
+    *DB_FILE.write().unwrap() = db_file;
+    let connection = new_connection()?;
+    connection.execute("PRAGMA journal_mode = WAL")?;
+    migrations::run(&connection)
 }
 
-pub fn insert_zitat(zitat_msg: &Message) {
+
+pub fn insert_zitat(zitat_msg: &Message, guild: u64) -> Result<()> {
     let SerenityUserId(author_id) = zitat_msg.author.id;
     let msg_id = zitat_msg.id.as_u64();
-    let author = match user::get(&author_id) {
-        Some(user_data) => user_data,
-        None => {
-            log("Author not found in DB", "WARN");
-            user::add(author_id, &zitat_msg.author.name);
-            User::new(author_id, zitat_msg.author.name.clone()) 
-        }
-    };
+    let author = user::get_or_add(author_id, &zitat_msg.author.name)?;
 
-    let connection = new_connection();
-    let mut statement = connection.prepare("INSERT INTO zitate(id, text, time, writer) VALUES(:id, :text, :time, :writer)").unwrap();
-    statement.bind((":id", *msg_id as i64)).unwrap();
-    statement.bind((":text", zitat_msg.content.trim())).unwrap();
-    statement.bind((":time", zitat_msg.timestamp.to_rfc3339().as_str())).unwrap();
-    statement.bind((":writer", author.id as i64)).unwrap();
-    let _ = statement.next();
-    log(&format!("Zitat with ID {msg_id} successfully inserted into DB"), "INFO");
+    insert_zitat_row(*msg_id, zitat_msg.content.trim(), &zitat_msg.timestamp.to_rfc3339(), author.id, guild, zitat_msg.channel_id.0)
 }
 
-pub fn delete_zitat(id: u64) {
-    let connection = new_connection();
-    let mut statement = connection.prepare("SELECT z.text AS content, datetime(z.time, 'unixepoch') AS timestamp, u.name AS author_name
+pub fn insert_zitat_row(id: u64, text: &str, time: &str, writer: u64, guild: u64, channel: u64) -> Result<()> {
+    let connection = new_connection()?;
+    let mut statement = connection.prepare("INSERT INTO zitate(id, text, time, writer, guild, channel) VALUES(:id, :text, :time, :writer, :guild, :channel)")?;
+    statement.bind((":id", id as i64))?;
+    statement.bind((":text", text))?;
+    statement.bind((":time", time))?;
+    statement.bind((":writer", writer as i64))?;
+    statement.bind((":guild", guild as i64))?;
+    statement.bind((":channel", channel as i64))?;
+    statement.next()?;
+
+    let mut statement = connection.prepare("INSERT INTO zitate_fts(rowid, text) VALUES(:id, :text)")?;
+    statement.bind((":id", id as i64))?;
+    statement.bind((":text", text))?;
+    statement.next()?;
+    stats::invalidate();
+    log(&format!("Zitat with ID {id} successfully inserted into DB"), "INFO");
+    Ok(())
+}
+
+pub fn get_newest_zitat_id(guild: u64) -> Result<Option<u64>> {
+    let connection = new_connection()?;
+    let mut statement = connection.prepare("SELECT max(id) FROM zitate WHERE guild = :guild")?;
+    statement.bind((":guild", guild as i64))?;
+    statement.next()?;
+    Ok(statement.read::<Option<i64>, _>(0)?.map(|id| id as u64))
+}
+
+/// Restored Zitate are left out, they were restored by hand although their message is gone.
+pub fn get_active_zitat_ids(guild: u64, channel: u64) -> Result<Vec<u64>> {
+    let connection = new_connection()?;
+    let mut statement = connection.prepare("SELECT id FROM active_zitate
+        WHERE guild = :guild AND channel = :channel AND restored_at IS NULL
+        ORDER BY id")?;
+    statement.bind((":guild", guild as i64))?;
+    statement.bind((":channel", channel as i64))?;
+    statement
+        .into_iter()
+        .map(|row| Ok(row?.read::<i64, _>("id") as u64))
+        .collect()
+}
+
+pub fn delete_zitat(id: u64) -> Result<()> {
+    let connection = new_connection()?;
+    let mut statement = connection.prepare("SELECT z.text AS content, z.time AS timestamp, u.name AS author_name
         FROM zitate AS z
         JOIN users AS u ON z.writer = u.id
         WHERE z.id = :id
-    ").unwrap();
-    statement.bind((":id", id as i64)).unwrap();
-    statement.next().unwrap();
+    ")?;
+    statement.bind((":id", id as i64))?;
+    if let sqlite::State::Done = statement.next()? {
+        return Err(Error::UserInput(format!("Zitat with ID {id} not found in DB")));
+    }
 
-    log(&format!("Content: {}", statement.read::<String, _>("content").unwrap()), "INFO");
-    log(&format!("Author:  {}", statement.read::<String, _>("author_name").unwrap()), "INFO");
-    log(&format!("Date:    {}", statement.read::<String, _>("timestamp").unwrap()), "INFO");
+    log(&format!("Content: {}", statement.read::<String, _>("content")?), "INFO");
+    log(&format!("Author:  {}", statement.read::<String, _>("author_name")?), "INFO");
+    log(&format!("Date:    {}", statement.read::<String, _>("timestamp")?), "INFO");
+    drop(statement);
 
-    connection.execute(format!("DELETE FROM zitate WHERE id = {id}")).unwrap();
+    connection.execute(format!("DELETE FROM zitate WHERE id = {id}"))?;
+    connection.execute(format!("DELETE FROM zitate_fts WHERE rowid = {id}"))?;
+    stats::invalidate();
     log("Deleted from DB", "INFO");
+    Ok(())
+}
+
+pub fn mark_zitat_deleted(id: u64) -> Result<()> {
+    let connection = new_connection()?;
+    let mut statement = connection.prepare("UPDATE zitate SET deleted_at = :time WHERE id = :id AND deleted_at IS NULL")?;
+    statement.bind((":time", Utc::now().to_rfc3339().as_str()))?;
+    statement.bind((":id", id as i64))?;
+    statement.next()?;
+    drop(statement);
+    if connection.change_count() == 0 {
+        log(&format!("Zitat with ID {id} not found in DB or already deleted"), "WARN");
+        return Ok(());
+    }
+    stats::invalidate();
+    log(&format!("Marked Zitat with ID {id} as deleted in DB"), "INFO");
+    Ok(())
+}
+
+pub fn restore_zitat(id: u64) -> Result<bool> {
+    let connection = new_connection()?;
+    let mut statement = connection.prepare("UPDATE zitate SET deleted_at = NULL, restored_at = :time WHERE id = :id AND deleted_at IS NOT NULL")?;
+    statement.bind((":time", Utc::now().to_rfc3339().as_str()))?;
+    statement.bind((":id", id as i64))?;
+    statement.next()?;
+    drop(statement);
+    if connection.change_count() == 0 {
+        return Ok(false);
+    }
+    stats::invalidate();
+    log(&format!("Restored Zitat with ID {id} in DB"), "INFO");
+    Ok(true)
+}
+
+pub fn update_zitat_text(id: u64, new_text: &str, edited_at: &str) -> Result<()> {
+    let connection = new_connection()?;
+
+    let mut statement = connection.prepare("SELECT text FROM zitate WHERE id = :id")?;
+    statement.bind((":id", id as i64))?;
+    if let sqlite::State::Done = statement.next()? {
+        return Ok(());
+    }
+    let old_text = statement.read::<String, _>(0)?;
+    drop(statement);
+
+    if old_text == new_text {
+        return Ok(());
+    }
+    log(
+        &format!("Changing content of Zitat with ID {id}:"),
+        "INFO",
+    );
+    log(&old_text, "INFO");
+    log("->", "INFO");
+    log(new_text, "INFO");
+
+    connection.execute("BEGIN TRANSACTION")?;
+    let mut statement = connection.prepare("INSERT INTO zitat_edits(zitat, text, replaced_at) VALUES(:id, :text, :time)")?;
+    statement.bind((":id", id as i64))?;
+    statement.bind((":text", old_text.as_str()))?;
+    statement.bind((":time", edited_at))?;
+    statement.next()?;
+
+    let mut statement = connection.prepare("UPDATE zitate SET text = :text WHERE id = :id")?;
+    statement.bind((":text", new_text))?;
+    statement.bind((":id", id as i64))?;
+    statement.next()?;
+
+    let mut statement = connection.prepare("UPDATE zitate_fts SET text = :text WHERE rowid = :id")?;
+    statement.bind((":text", new_text))?;
+    statement.bind((":id", id as i64))?;
+    statement.next()?;
+    drop(statement);
+    connection.execute("COMMIT")?;
+
+    log("Zitat successfully updated", "INFO");
+    Ok(())
+}
+
+pub fn get_zitat_history(id: u64) -> Result<String> {
+    let connection = new_connection()?;
+    let mut statement = connection.prepare("SELECT id, text, guild, channel FROM zitate WHERE id = :id")?;
+    statement.bind((":id", id as i64))?;
+    let current = match statement.into_iter().next() {
+        Some(row) => row?,
+        None => return Ok(String::from("Zitat nicht gefunden")),
+    };
+    let current_text = current.read::<&str, _>("text");
+
+    let mut statement = connection.prepare("SELECT text, replaced_at FROM zitat_edits WHERE zitat = :id ORDER BY id")?;
+    statement.bind((":id", id as i64))?;
+    let mut versions: Vec<String> = statement.into_iter().map(|row| {
+        let row = row?;
+        let replaced_at = DateTime::parse_from_rfc3339(row.read::<&str, _>("replaced_at"))
+            .map(|time| time.with_timezone(&Local).format("%d.%m.%Y %H:%M").to_string())
+            .unwrap_or_else(|_| row.read::<&str, _>("replaced_at").to_string());
+        Ok(format!("Bis {replaced_at}:\n{}", row.read::<&str, _>("text")))
+    }).collect::<Result<_>>()?;
+    if versions.is_empty() {
+        return Ok(format!("{}\nwurde noch nie bearbeitet", format_zitat(&current)));
+    }
+    versions.push(format!("Aktuell:\n{current_text}"));
+    Ok(format!("Verlauf von Zitat {id}:\n\n{}", versions.join("\n------------------\n")))
+}
+
+pub fn channel_link(guild: u64, channel: u64) -> String {
+    format!("https://discord.com/channels/{guild}/{channel}")
+}
+
+pub fn message_link(guild: u64, channel: u64, message: u64) -> String {
+    format!("{}/{message}", channel_link(guild, channel))
+}
+
+// Expects the columns id, text, guild and channel of a Zitat
+pub fn format_zitat(row: &sqlite::Row) -> String {
+    let text = row.read::<&str, _>("text");
+    match (row.read::<Option<i64>, _>("guild"), row.read::<Option<i64>, _>("channel")) {
+        (Some(guild), Some(channel)) => format!("{text}\n{}", message_link(guild as u64, channel as u64, row.read::<i64, _>("id") as u64)),
+        _ => text.to_string(),
+    }
+}
+
+pub fn get_formatted_zitat(id: u64) -> Result<Option<String>> {
+    let connection = new_connection()?;
+    let mut statement = connection.prepare("SELECT id, text, guild, channel FROM zitate WHERE id = :id")?;
+    statement.bind((":id", id as i64))?;
+    match statement.into_iter().next() {
+        Some(row) => Ok(Some(format_zitat(&row?))),
+        None => Ok(None),
+    }
+}
+
+pub fn get_open_zitate(guild: u64) -> Result<String> {
+    let connection = new_connection()?;
+    let mut statement = connection.prepare("
+        SELECT z.id AS id, z.text AS text, z.guild AS guild, z.channel AS channel, t.thread AS thread
+        FROM active_zitate AS z
+        LEFT JOIN said AS s ON s.zitat = z.id
+        LEFT JOIN qa_threads AS t ON t.zitat = z.id
+        WHERE s.zitat IS NULL AND z.guild = :guild
+        ORDER BY z.id
+    ")?;
+    statement.bind((":guild", guild as i64))?;
+    let zitate: Vec<String> = statement.into_iter().map(|row| {
+        let row = row?;
+        let thread = match row.read::<Option<i64>, _>("thread") {
+            Some(thread) => format!("Thread: {}", channel_link(guild, thread as u64)),
+            None => String::from("Kein Thread vorhanden"),
+        };
+        Ok(format!("{}\n{thread}", format_zitat(&row)))
+    }).collect::<Result<_>>()?;
+    if zitate.is_empty() {
+        Ok(String::from("Alle Zitate sind eingetragen"))
+    } else {
+        Ok(format!("Offene Zitate ({}):\n\n{}", zitate.len(), zitate.join("\n------------------\n")))
+    }
+}
+
+pub fn search_zitate(query: &str, filter: ZitatFilter) -> Result<String> {
+    let fts_query = query
+        .split_whitespace()
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+        .collect::<Vec<String>>()
+        .join(" ");
+    if fts_query.is_empty() {
+        return Err(Error::UserInput(String::from("Du musst nach etwas suchen.")));
+    }
+
+
+    let connection = new_connection()?;
+    let mut statement = connection.prepare(format!("
+        SELECT z.id AS id, z.text AS text, z.guild AS guild, z.channel AS channel
+        FROM zitate_fts AS f
+        JOIN active_zitate AS z ON z.id = f.rowid
+        WHERE zitate_fts MATCH :query AND {}
+        ORDER BY f.rank
+        LIMIT 25
+    ", filter.sql_condition()))?;
+    statement.bind((":query", fts_query.as_str()))?;
+    filter.bind(&mut statement)?;
+
+    let zitate: Vec<String> = statement.into_iter().map(|row| Ok(format_zitat(&row?))).collect::<Result<_>>()?;
+    if zitate.is_empty() {
+        Ok(format!("Keine Zitate zu \"{query}\" gefunden"))
+    } else {
+        Ok(format!("Suchergebnisse für \"{query}\":\n\n{}", zitate.join("\n------------------\n")))
+    }
+}
+
+pub fn get_random_zitat(filter: ZitatFilter) -> Result<String> {
+    let connection = new_connection()?;
+    let mut statement = connection.prepare(format!("
+        SELECT z.id AS id, z.text AS text, z.guild AS guild, z.channel AS channel
+        FROM active_zitate AS z
+        WHERE {}
+        ORDER BY RANDOM()
+        LIMIT 1
+    ", filter.sql_condition()))?;
+    filter.bind(&mut statement)?;
+    if let Some(row) = statement.into_iter().next() {
+        Ok(format_zitat(&row?))
+    } else {
+        Ok(String::from("Keine passenden Zitate gefunden"))
+    }
 }
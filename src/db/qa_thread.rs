@@ -0,0 +1,112 @@
+use chrono::Utc;
+
+use crate::{db::new_connection, error::Result};
+
+pub fn insert(zitat: u64, thread: u64) -> Result<()> {
+    let connection = new_connection()?;
+    let mut statement = connection.prepare("INSERT INTO qa_threads(zitat, thread) VALUES(:zitat, :thread)
+        ON CONFLICT(zitat) DO UPDATE SET thread = :thread")?;
+    statement.bind((":zitat", zitat as i64))?;
+    statement.bind((":thread", thread as i64))?;
+    statement.next()?;
+    Ok(())
+}
+
+pub fn delete(zitat: u64) -> Result<()> {
+    let connection = new_connection()?;
+    let mut statement = connection.prepare("DELETE FROM qa_threads WHERE zitat = :zitat")?;
+    statement.bind((":zitat", zitat as i64))?;
+    statement.next()?;
+    Ok(())
+}
+
+pub fn get_status_message(zitat: u64) -> Result<Option<u64>> {
+    let connection = new_connection()?;
+    let mut statement = connection.prepare("SELECT status_message FROM qa_threads WHERE zitat = :zitat")?;
+    statement.bind((":zitat", zitat as i64))?;
+    if let sqlite::State::Row = statement.next()? {
+        Ok(statement.read::<Option<i64>, _>("status_message")?.map(|id| id as u64))
+    } else {
+        Ok(None)
+    }
+}
+
+pub fn set_status_message(zitat: u64, message: u64) -> Result<()> {
+    let connection = new_connection()?;
+    let mut statement = connection.prepare("UPDATE qa_threads SET status_message = :message WHERE zitat = :zitat")?;
+    statement.bind((":zitat", zitat as i64))?;
+    statement.bind((":message", message as i64))?;
+    statement.next()?;
+    Ok(())
+}
+
+pub fn get_thread(zitat: u64) -> Result<Option<u64>> {
+    let connection = new_connection()?;
+    let mut statement = connection.prepare("SELECT thread FROM qa_threads WHERE zitat = :zitat")?;
+    statement.bind((":zitat", zitat as i64))?;
+    if let sqlite::State::Row = statement.next()? {
+        Ok(Some(statement.read::<i64, _>("thread")? as u64))
+    } else {
+        Ok(None)
+    }
+}
+
+pub fn get_unattributed(guild: u64) -> Result<Vec<u64>> {
+    let connection = new_connection()?;
+    let mut statement = connection.prepare("SELECT z.id AS id FROM active_zitate AS z
+        WHERE z.guild = :guild AND NOT EXISTS (SELECT 1 FROM said AS s WHERE s.zitat = z.id)
+        ORDER BY z.id")?;
+    statement.bind((":guild", guild as i64))?;
+    statement
+        .into_iter()
+        .map(|row| Ok(row?.read::<i64, _>("id") as u64))
+        .collect()
+}
+
+fn get_unattributed_older_than(days: u64, reminder_column: &str) -> Result<Vec<(u64, u64)>> {
+    let connection = new_connection()?;
+    let mut statement = connection.prepare(format!("SELECT z.id AS id, z.writer AS writer
+        FROM active_zitate AS z
+        LEFT JOIN qa_reminders AS r ON r.zitat = z.id
+        WHERE NOT EXISTS (SELECT 1 FROM said AS s WHERE s.zitat = z.id)
+        AND datetime(z.time) < datetime('now', :age)
+        AND r.{reminder_column} IS NULL
+        ORDER BY z.id"))?;
+    statement.bind((":age", format!("-{days} days").as_str()))?;
+    statement
+        .into_iter()
+        .map(|row| {
+            let row = row?;
+            Ok((row.read::<i64, _>("id") as u64, row.read::<i64, _>("writer") as u64))
+        })
+        .collect()
+}
+
+fn mark_reminded(zitat: u64, reminder_column: &str) -> Result<()> {
+    let connection = new_connection()?;
+    let mut statement = connection.prepare(format!("INSERT INTO qa_reminders(zitat, {reminder_column}) VALUES(:zitat, :time)
+        ON CONFLICT(zitat) DO UPDATE SET {reminder_column} = :time"))?;
+    statement.bind((":zitat", zitat as i64))?;
+    statement.bind((":time", Utc::now().to_rfc3339().as_str()))?;
+    statement.next()?;
+    Ok(())
+}
+
+pub fn get_due_writer_reminders(days: u64) -> Result<Vec<(u64, u64)>> {
+    get_unattributed_older_than(days, "writer_reminded_at")
+}
+
+pub fn mark_writer_reminded(zitat: u64) -> Result<()> {
+    mark_reminded(zitat, "writer_reminded_at")
+}
+
+pub fn get_due_owner_reminders(days: u64) -> Result<Vec<u64>> {
+    Ok(get_unattributed_older_than(days, "owner_notified_at")?
+        .into_iter()
+        .map(|(zitat, _)| zitat)
+        .collect())
+}
+
+pub fn mark_owner_notified(zitat: u64) -> Result<()> {
+    mark_reminded(zitat, "owner_notified_at")
+}
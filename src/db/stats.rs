@@ -0,0 +1,102 @@
+use std::{collections::BTreeMap, sync::Mutex};
+
+use crate::{db::{new_connection, TimeWindow}, error::Result};
+
+struct CachedCount {
+    generation: u64,
+    counts: BTreeMap<u64, i64>,
+}
+
+static ZITATE_COUNT: Mutex<CachedCount> = Mutex::new(CachedCount { generation: 0, counts: BTreeMap::new() });
+
+pub fn total_zitate(guild: u64) -> Result<i64> {
+    let generation = {
+        let cached = ZITATE_COUNT.lock().unwrap();
+        if let Some(count) = cached.counts.get(&guild) {
+            return Ok(*count);
+        }
+        cached.generation
+    };
+    let count = query_count(guild, &TimeWindow::default())?;
+    let mut cached = ZITATE_COUNT.lock().unwrap();
+    if cached.generation == generation {
+        cached.counts.insert(guild, count);
+    }
+    Ok(count)
+}
+
+pub fn count_zitate(guild: u64, window: &TimeWindow) -> Result<i64> {
+    if window.from.is_none() && window.to.is_none() {
+        total_zitate(guild)
+    } else {
+        query_count(guild, window)
+    }
+}
+
+pub fn invalidate() {
+    let mut cached = ZITATE_COUNT.lock().unwrap();
+    cached.generation += 1;
+    cached.counts.clear();
+}
+
+pub fn get_percentage(count: i64, total: i64) -> f32 {
+    if total == 0 {
+        return 0.0;
+    }
+    (count as f32 * 10_000.0 / total as f32).round() / 100.0
+}
+
+fn query_count(guild: u64, window: &TimeWindow) -> Result<i64> {
+    let connection = new_connection()?;
+    let mut statement = connection.prepare(format!(
+        "SELECT count(*) AS count FROM active_zitate AS z WHERE z.guild = :guild AND {}",
+        window.sql_condition("z.time")
+    ))?;
+    statement.bind((":guild", guild as i64))?;
+    statement.next()?;
+    Ok(statement.read::<i64, _>("count")?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process, thread, time::{SystemTime, UNIX_EPOCH}};
+
+    use super::*;
+    use crate::db::{delete_zitat, insert_zitat_row, mark_zitat_deleted, restore_zitat, setup, user};
+
+    #[test]
+    fn concurrent_register_and_remove_keep_count_consistent() {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        let db_file = std::env::temp_dir().join(format!("zitate_bot_stats_test_{}_{nanos}.db", process::id()));
+        setup(db_file.to_str().unwrap().to_string()).unwrap();
+        user::add(1, "writer").unwrap();
+
+        let threads: Vec<_> = (0..8u64).map(|t| thread::spawn(move || {
+            for i in 0..20u64 {
+                let id = t * 100 + i;
+                insert_zitat_row(id, "Zitat", "2024-01-01T12:00:00+00:00", 1, 1, 10).unwrap();
+                total_zitate(1).unwrap();
+                match i % 4 {
+                    0 => delete_zitat(id).unwrap(),
+                    1 => mark_zitat_deleted(id).unwrap(),
+                    _ => (),
+                }
+            }
+        })).collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(total_zitate(1).unwrap(), 8 * 10);
+        assert_eq!(total_zitate(1).unwrap(), query_count(1, &TimeWindow::default()).unwrap());
+        assert_eq!(total_zitate(2).unwrap(), 0);
+
+        assert!(restore_zitat(1).unwrap());
+        assert_eq!(total_zitate(1).unwrap(), 8 * 10 + 1);
+        mark_zitat_deleted(2).unwrap();
+        assert_eq!(total_zitate(1).unwrap(), 8 * 10);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{}{suffix}", db_file.display()));
+        }
+    }
+}
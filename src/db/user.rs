@@ -1,4 +1,4 @@
-use crate::{db::{get_percentage, new_connection}, logging::log};
+use crate::{db::{format_zitat, new_connection, stats}, error::Result, logging::log};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize)]
@@ -21,35 +21,100 @@ pub enum Identifier<'a> {
     Name(&'a String),
 }
 
-fn get_by_uid(id: &u64) -> Option<User> {
-    let connection = new_connection();
+fn get_by_uid(id: &u64) -> Result<Option<User>> {
+    let connection = new_connection()?;
     let mut statement = connection.prepare("SELECT u.id AS main_id, u.name AS name
         FROM users as u
         LEFT JOIN other_ids AS o ON u.id = o.main_id
-        WHERE u.id = :id OR o.secondary_id = :id").unwrap();
-    statement.bind((":id", *id as i64)).unwrap();
-    if let sqlite::State::Row = statement.next().unwrap() {
-        let id = statement.read::<i64, _>("main_id").unwrap() as u64;
-        let name = statement.read::<String, _>("name").unwrap();
-        Some(User::new(id, name))
+        WHERE u.id = :id OR o.secondary_id = :id")?;
+    statement.bind((":id", *id as i64))?;
+    if let sqlite::State::Row = statement.next()? {
+        let id = statement.read::<i64, _>("main_id")? as u64;
+        let name = statement.read::<String, _>("name")?;
+        Ok(Some(User::new(id, name)))
     } else {
-        None
+        Ok(None)
     }
 }
 
-fn get_by_name(name: &str) -> Option<User> {
-    let connection = new_connection();
-    let mut statement = connection.prepare("SELECT * from users WHERE name = :name").unwrap();
-    let _ = statement.bind((":name", name));
-    if let sqlite::State::Row = statement.next().unwrap() {
-        let id = statement.read::<i64, _>("id").unwrap() as u64;
-        let name = statement.read::<String, _>("name").unwrap();
-        Some(User::new(id, name))
+// Users who wrote, said or assisted a Zitat of :guild, or everyone if :guild is NULL
+const GUILD_MEMBER_CONDITION: &str = "(:guild IS NULL OR u.id IN (
+    SELECT writer FROM zitate WHERE guild = :guild
+    UNION SELECT s.user FROM said AS s JOIN zitate AS z ON z.id = s.zitat WHERE z.guild = :guild
+    UNION SELECT a.user FROM assisted AS a JOIN zitate AS z ON z.id = a.zitat WHERE z.guild = :guild
+))";
+
+fn get_by_name(name: &str, guild: Option<u64>) -> Result<Option<User>> {
+    let connection = new_connection()?;
+    let name = name.trim();
+
+    let mut statement = connection.prepare("SELECT id, name FROM users
+        WHERE name = :name COLLATE NOCASE
+        ORDER BY name = :name DESC
+        LIMIT 1")?;
+    statement.bind((":name", name))?;
+    if let sqlite::State::Row = statement.next()? {
+        let id = statement.read::<i64, _>("id")? as u64;
+        let name = statement.read::<String, _>("name")?;
+        return Ok(Some(User::new(id, name)));
+    }
+
+    let mut statement = connection.prepare("SELECT u.id AS id, u.name AS name
+        FROM name_aliases AS a
+        JOIN users AS u ON a.user = u.id
+        WHERE a.alias = :name")?;
+    statement.bind((":name", name))?;
+    if let sqlite::State::Row = statement.next()? {
+        let id = statement.read::<i64, _>("id")? as u64;
+        let name = statement.read::<String, _>("name")?;
+        return Ok(Some(User::new(id, name)));
+    }
+
+    let prefix = format!("{}%", escape_like(name));
+    let mut statement = connection.prepare(format!("SELECT DISTINCT u.id AS id, u.name AS name
+        FROM users AS u
+        LEFT JOIN name_aliases AS a ON a.user = u.id
+        WHERE (u.name LIKE :prefix ESCAPE '\\' OR a.alias LIKE :prefix ESCAPE '\\') AND {GUILD_MEMBER_CONDITION}
+        LIMIT 2"))?;
+    statement.bind((":prefix", prefix.as_str()))?;
+    statement.bind((":guild", guild.map(|id| id as i64)))?;
+    let matches: Vec<User> = statement
+        .into_iter()
+        .map(|row| {
+            let row = row?;
+            Ok(User::new(row.read::<i64, _>("id") as u64, row.read::<&str, _>("name").to_string()))
+        })
+        .collect::<Result<_>>()?;
+    if matches.len() == 1 {
+        Ok(matches.into_iter().next())
     } else {
-        None
+        Ok(None)
     }
 }
 
+fn escape_like(input: &str) -> String {
+    input.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+pub fn get_name_suggestions(input: &str, guild: u64) -> Result<Vec<String>> {
+    let connection = new_connection()?;
+    let input = escape_like(input.trim());
+    let mut statement = connection.prepare(format!("SELECT u.name AS name
+        FROM users AS u
+        LEFT JOIN name_aliases AS a ON a.user = u.id
+        WHERE (u.name LIKE :pattern ESCAPE '\\' OR a.alias LIKE :pattern ESCAPE '\\') AND {GUILD_MEMBER_CONDITION}
+        GROUP BY u.id
+        ORDER BY max(u.name LIKE :prefix ESCAPE '\\', coalesce(a.alias LIKE :prefix ESCAPE '\\', 0)) DESC, u.name COLLATE NOCASE
+        LIMIT 25"))?;
+    statement.bind((":pattern", format!("%{input}%").as_str()))?;
+    statement.bind((":prefix", format!("{input}%").as_str()))?;
+    statement.bind((":guild", guild as i64))?;
+    statement
+        .into_iter()
+        .map(|row| Ok(row?.read::<&str, _>("name").to_string()))
+        .collect()
+}
+
 impl<'a> From<&'a u64> for Identifier<'a> {
     fn from(val: &'a u64) -> Self {
         Identifier::Id(val)
@@ -61,82 +126,226 @@ impl<'a> From<&'a String> for Identifier<'a> {
     }
 }
 
-pub fn get<'a, T>(user: T) -> Option<User> 
+pub fn get<'a, T>(user: T) -> Result<Option<User>>
     where T: Into<Identifier<'a>> {
         match user.into() {
             Identifier::Id(id) => get_by_uid(id),
-            Identifier::Name(name) => get_by_name(name),
+            Identifier::Name(name) => get_by_name(name, None),
         }
 }
 
-pub fn get_id<'a, T>(user: T) -> Option<u64>
+/// Like `get` with a name, but a prefix only matches users who took part in a Zitat of the guild.
+pub fn get_in_guild(name: &str, guild: u64) -> Result<Option<User>> {
+    get_by_name(name, Some(guild))
+}
+
+pub fn get_id<'a, T>(user: T) -> Result<Option<u64>>
     where T: Into<Identifier<'a>> {
         match user.into() {
-            Identifier::Id(id) => Some(*id),
-            Identifier::Name(name) => match get_by_name(name) {
-                Some(user) => Some(user.id),
-                None => None,
-            }
+            Identifier::Id(id) => Ok(Some(*id)),
+            Identifier::Name(name) => Ok(get_by_name(name, None)?.map(|user| user.id)),
         }
 }
 
-pub fn add(id: u64, name: &str) {
-    let connection = new_connection();
-    let mut statement = connection.prepare("INSERT INTO users(id, name) VALUES(:id, :name)").unwrap();
-    let _ = statement.bind((":id", id as i64));
-    let _ = statement.bind((":name", name));
-    let _ = statement.next();
+pub fn add(id: u64, name: &str) -> Result<()> {
+    let connection = new_connection()?;
+    let mut statement = connection.prepare("INSERT INTO users(id, name) VALUES(:id, :name)")?;
+    statement.bind((":id", id as i64))?;
+    statement.bind((":name", name))?;
+    statement.next()?;
     log(&format!("Added {name} to DB"), "INFO");
+    Ok(())
 }
 
-pub fn get_stats(user: User) -> String {
-    let connection = new_connection();
+pub fn get_or_add(id: u64, name: &str) -> Result<User> {
+    if let Some(user) = get(&id)? {
+        return Ok(user);
+    }
+    log(&format!("User {name} ({id}) not found in DB"), "WARN");
+    // The name is taken by someone else, so fall back to one that is unique
+    let name = if add(id, name).is_ok() {
+        name.to_string()
+    } else {
+        let fallback = format!("{name} ({id})");
+        add(id, &fallback)?;
+        fallback
+    };
+    Ok(User::new(id, name))
+}
 
-    let mut statement = connection.prepare("SELECT count(user) AS count FROM said WHERE user = :id").unwrap();
-    let _ = statement.bind((":id", user.id as i64));
-    let _ = statement.next();
-    let said = statement.read::<i64, _>("count").unwrap();
+pub fn get_stats(user: User, guild: u64) -> Result<String> {
+    let connection = new_connection()?;
 
-    let mut statement = connection.prepare("SELECT count(writer) AS count FROM zitate WHERE writer = :id").unwrap();
-    let _ = statement.bind((":id", user.id as i64));
-    let _ = statement.next();
-    let wrote = statement.read::<i64, _>("count").unwrap();
+    let mut statement = connection.prepare("SELECT count(s.user) AS count FROM said AS s JOIN active_zitate AS z ON s.zitat = z.id WHERE s.user = :id AND z.guild = :guild")?;
+    statement.bind((":id", user.id as i64))?;
+    statement.bind((":guild", guild as i64))?;
+    statement.next()?;
+    let said = statement.read::<i64, _>("count")?;
 
-    let mut statement = connection.prepare("SELECT count(user) AS count FROM assisted WHERE user = :id").unwrap();
-    let _ = statement.bind((":id", user.id as i64));
-    let _ = statement.next();
-    let assisted = statement.read::<i64, _>("count").unwrap();
+    let mut statement = connection.prepare("SELECT count(writer) AS count FROM active_zitate WHERE writer = :id AND guild = :guild")?;
+    statement.bind((":id", user.id as i64))?;
+    statement.bind((":guild", guild as i64))?;
+    statement.next()?;
+    let wrote = statement.read::<i64, _>("count")?;
 
-    format!(
+    let mut statement = connection.prepare("SELECT count(a.user) AS count FROM assisted AS a JOIN active_zitate AS z ON a.zitat = z.id WHERE a.user = :id AND z.guild = :guild")?;
+    statement.bind((":id", user.id as i64))?;
+    statement.bind((":guild", guild as i64))?;
+    statement.next()?;
+    let assisted = statement.read::<i64, _>("count")?;
+
+    let total = stats::total_zitate(guild)?;
+    Ok(format!(
         "Stats für {}:\nGesagt: {said} ({}%)\nGeschrieben: {wrote} ({}%)\nAssisted: {assisted} ({}%)",
         user.name,
-        get_percentage(said),
-        get_percentage(wrote),
-        get_percentage(assisted)
-    )
+        stats::get_percentage(said, total),
+        stats::get_percentage(wrote, total),
+        stats::get_percentage(assisted, total)
+    ))
 }
 
-pub fn get_zitate(user: User) -> String {
-    let connection = new_connection();
+pub fn get_zitate(user: User, guild: u64) -> Result<String> {
+    let connection = new_connection()?;
     let mut statement = connection.prepare("
-        SELECT z.id as id, z.text as text
-        FROM zitate AS z
+        SELECT z.id as id, z.text as text, z.guild AS guild, z.channel AS channel
+        FROM active_zitate AS z
         JOIN said AS s ON z.id = s.zitat
-        WHERE s.user = :user_id
+        WHERE s.user = :user_id AND z.guild = :guild
         ORDER BY id
-    ").unwrap();
-    let _ = statement.bind((":user_id", user.id as i64));
-
-    let zitate: Vec<String> = statement.into_iter().map(|row| {
-        let row = row.unwrap();
-        format!("{}\nhttps://discord.com/channels/422796692899758091/528316171389239296/{}",
-            row.read::<&str, _>("text"),
-            row.read::<i64, _>("id")
-        )
-    }).collect();
+    ")?;
+    statement.bind((":user_id", user.id as i64))?;
+    statement.bind((":guild", guild as i64))?;
+
+    let zitate: Vec<String> = statement.into_iter().map(|row| Ok(format_zitat(&row?))).collect::<Result<_>>()?;
     if zitate.is_empty() {
-        format!("{} hat noch keine Zitate", user.name)
+        Ok(format!("{} hat noch keine Zitate", user.name))
+    } else {
+        Ok(format!("Zitate von {}:\n\n{}", user.name, zitate.join("\n------------------\n")))
+    }
+}
+
+pub fn link(main: User, secondary_id: u64) -> Result<String> {
+    if main.id == secondary_id {
+        return Ok(String::from("Ein Account kann nicht mit sich selbst verknüpft werden."));
+    }
+    let connection = new_connection()?;
+
+    let mut statement = connection.prepare("SELECT name FROM users WHERE id = :id")?;
+    statement.bind((":id", secondary_id as i64))?;
+    if let sqlite::State::Row = statement.next()? {
+        return Ok(format!(
+            "{secondary_id} ist bereits als eigener Nutzer {} eingetragen.",
+            statement.read::<String, _>("name")?
+        ));
+    }
+
+    let mut statement = connection.prepare("SELECT u.name AS name
+        FROM other_ids AS o
+        JOIN users AS u ON o.main_id = u.id
+        WHERE o.secondary_id = :id")?;
+    statement.bind((":id", secondary_id as i64))?;
+    if let sqlite::State::Row = statement.next()? {
+        return Ok(format!(
+            "{secondary_id} ist bereits mit {} verknüpft.",
+            statement.read::<String, _>("name")?
+        ));
+    }
+
+    let mut statement = connection.prepare("SELECT * FROM other_ids WHERE secondary_id = :id")?;
+    statement.bind((":id", main.id as i64))?;
+    if let sqlite::State::Row = statement.next()? {
+        return Ok(format!("{} ist selbst nur ein Zweitaccount.", main.name));
+    }
+    drop(statement);
+
+    let mut statement = connection.prepare("INSERT INTO other_ids(secondary_id, main_id) VALUES(:secondary_id, :main_id)")?;
+    statement.bind((":secondary_id", secondary_id as i64))?;
+    statement.bind((":main_id", main.id as i64))?;
+    if let Err(why) = statement.next() {
+        log(&format!("Could not link {secondary_id} to {}: {why}", main.name), "ERR ");
+        return Ok(String::from("Verknüpfung fehlgeschlagen."));
+    }
+    log(&format!("Linked {secondary_id} to {} in DB", main.name), "INFO");
+    Ok(format!("{secondary_id} erfolgreich mit {} verknüpft.", main.name))
+}
+
+pub fn unlink(secondary_id: u64) -> Result<String> {
+    let connection = new_connection()?;
+    let mut statement = connection.prepare("DELETE FROM other_ids WHERE secondary_id = :id")?;
+    statement.bind((":id", secondary_id as i64))?;
+    statement.next()?;
+    drop(statement);
+    if connection.change_count() == 0 {
+        return Ok(format!("{secondary_id} ist mit niemandem verknüpft."));
+    }
+    log(&format!("Unlinked {secondary_id} in DB"), "INFO");
+    Ok(format!("Verknüpfung von {secondary_id} entfernt."))
+}
+
+pub fn add_nickname(user: User, alias: &str) -> Result<String> {
+    let alias = alias.trim();
+    if alias.is_empty() {
+        return Ok(String::from("Der Spitzname darf nicht leer sein."));
+    }
+    let connection = new_connection()?;
+    let mut statement = connection.prepare("SELECT name FROM users WHERE name = :alias COLLATE NOCASE AND id != :id")?;
+    statement.bind((":alias", alias))?;
+    statement.bind((":id", user.id as i64))?;
+    if let sqlite::State::Row = statement.next()? {
+        return Ok(format!("{alias} ist bereits der Name von {}.", statement.read::<String, _>("name")?));
+    }
+    drop(statement);
+
+    let mut statement = connection.prepare("INSERT INTO name_aliases(alias, user) VALUES(:alias, :user)")?;
+    statement.bind((":alias", alias))?;
+    statement.bind((":user", user.id as i64))?;
+    if statement.next().is_err() {
+        return Ok(format!("{alias} wird bereits als Spitzname verwendet."));
+    }
+    log(&format!("Added nickname {alias} for {} to DB", user.name), "INFO");
+    Ok(format!("{alias} ist jetzt ein Spitzname von {}.", user.name))
+}
+
+pub fn remove_nickname(alias: &str) -> Result<String> {
+    let connection = new_connection()?;
+    let mut statement = connection.prepare("DELETE FROM name_aliases WHERE alias = :alias")?;
+    statement.bind((":alias", alias.trim()))?;
+    statement.next()?;
+    drop(statement);
+    if connection.change_count() == 0 {
+        return Ok(format!("{alias} ist kein Spitzname."));
+    }
+    log(&format!("Removed nickname {alias} from DB"), "INFO");
+    Ok(format!("Spitzname {alias} entfernt."))
+}
+
+fn get_nicknames(user: &User) -> Result<Vec<String>> {
+    let connection = new_connection()?;
+    let mut statement = connection.prepare("SELECT alias FROM name_aliases WHERE user = :id ORDER BY alias")?;
+    statement.bind((":id", user.id as i64))?;
+    statement
+        .into_iter()
+        .map(|row| Ok(row?.read::<&str, _>("alias").to_string()))
+        .collect()
+}
+
+pub fn get_aliases(user: User) -> Result<String> {
+    let connection = new_connection()?;
+    let mut statement = connection.prepare("SELECT secondary_id FROM other_ids WHERE main_id = :id ORDER BY secondary_id")?;
+    statement.bind((":id", user.id as i64))?;
+    let aliases: Vec<String> = statement
+        .into_iter()
+        .map(|row| Ok(row?.read::<i64, _>("secondary_id").to_string()))
+        .collect::<Result<_>>()?;
+    let accounts = if aliases.is_empty() {
+        format!("{} hat keine weiteren Accounts", user.name)
+    } else {
+        format!("Weitere Accounts von {} ({}):\n{}", user.name, user.id, aliases.join("\n"))
+    };
+    let nicknames = get_nicknames(&user)?;
+    if nicknames.is_empty() {
+        Ok(accounts)
     } else {
-        format!("Zitate von {}:\n\n{}", user.name, zitate.join("\n------------------\n"))
+        Ok(format!("{accounts}\nSpitznamen: {}", nicknames.join(", ")))
     }
 }
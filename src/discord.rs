@@ -1,101 +1,256 @@
-use pml::PmlStruct;
 use serenity::{
-    model::{id::ChannelId, prelude::{Activity, Message, ChannelType, GuildId}, channel::Channel::Guild as GuildChannel},
+    model::{id::ChannelId, prelude::{Activity, Message, ChannelType, GuildId}, channel::{Channel::Guild as GuildChannel, ThreadsData}},
     prelude::{Context, Client, GatewayIntents},
 };
-use std::{env, sync::{mpsc::Sender, Arc, Mutex}};
-use crate::{logging::log, event_handler::Handler};
+use std::{collections::HashMap, env, sync::{mpsc::Sender, Arc, Mutex}, time::Duration};
+use crate::{config::SharedConfig, db::{get_attributions, guild::Guild, message_link, qa_thread}, error::{Error, Result}, logging::log, event_handler::Handler};
 
-pub async fn delete_qa_thread(msg_id: String, ctx: &Context, config: &pml::PmlStruct) {
-    let channel = GuildId(config.get("guildId").expect("guildId value not found in config file"))
+const MAX_ATTEMPTS: u32 = 5;
+
+// serenity 0.11 appends `before` and `limit` to this route without a `?`, so the request is built by hand.
+// That also bypasses serenity's rate limiter, so rate limits are handled here
+async fn get_archived_threads(channel_id: u64, before: Option<&str>, ctx: &Context) -> serenity::Result<ThreadsData> {
+    let mut url = format!("https://discord.com/api/v10/channels/{channel_id}/threads/archived/public?limit=100");
+    if let Some(before) = before {
+        url.push_str(&format!("&before={before}"));
+    }
+    let client = reqwest::Client::new();
+    let mut attempt = 1;
+    loop {
+        let response = client
+            .get(&url)
+            .header(reqwest::header::AUTHORIZATION, &ctx.http.token)
+            .send()
+            .await?;
+        let header = |name: &str| response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<f64>().ok())
+            .filter(|seconds| seconds.is_finite() && *seconds >= 0.0);
+        if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS && attempt < MAX_ATTEMPTS {
+            let retry_after = header("retry-after").unwrap_or(1.0);
+            log(&format!("Rate limited while fetching archived threads, retrying in {retry_after}s"), "WARN");
+            tokio::time::sleep(Duration::from_secs_f64(retry_after)).await;
+            attempt += 1;
+            continue;
+        }
+        // Wait for an exhausted bucket to reset so the next page isn't rate limited
+        let reset_after = header("x-ratelimit-reset-after").filter(|_| header("x-ratelimit-remaining") == Some(0.0));
+        let threads = response.error_for_status()?.json().await?;
+        if let Some(reset_after) = reset_after {
+            tokio::time::sleep(Duration::from_secs_f64(reset_after)).await;
+        }
+        return Ok(threads);
+    }
+}
+
+/// Finds QA threads by their name, the threads of the bot channel are fetched from Discord at most once per lookup.
+pub struct ThreadLookup {
+    guild: u64,
+    channel_bot: u64,
+    // Whether found threads are saved to the DB, off for dry runs
+    record: bool,
+    threads: Option<HashMap<String, ChannelId>>,
+}
+
+impl ThreadLookup {
+    pub fn new(guild: &Guild, record: bool) -> Self {
+        Self { guild: guild.id, channel_bot: guild.channel_bot, record, threads: None }
+    }
+
+    pub async fn find(&mut self, zitat_id: u64, ctx: &Context) -> Result<Option<ChannelId>> {
+        if let Some(thread_id) = qa_thread::get_thread(zitat_id)? {
+            return Ok(Some(ChannelId(thread_id)));
+        }
+        if self.threads.is_none() {
+            self.threads = Some(self.fetch_threads(ctx).await?);
+        }
+        let thread_id = self.threads.as_ref().and_then(|threads| threads.get(&zitat_id.to_string()).copied());
+        if let Some(thread_id) = thread_id
+            && self.record
+        {
+            qa_thread::insert(zitat_id, thread_id.0)?;
+        }
+        Ok(thread_id)
+    }
+
+    async fn fetch_threads(&self, ctx: &Context) -> Result<HashMap<String, ChannelId>> {
+        let mut threads: HashMap<String, ChannelId> = GuildId(self.guild)
             .get_active_threads(&ctx.http)
-            .await
-            .unwrap()
+            .await?
             .threads
-            .iter()
-            .find(|thread| thread.name() == msg_id)
-            .unwrap()
-            .id;
+            .into_iter()
+            .filter(|thread| thread.parent_id == Some(ChannelId(self.channel_bot)))
+            .map(|thread| (thread.name, thread.id))
+            .collect();
+        let mut before = None;
+        loop {
+            let archived = get_archived_threads(self.channel_bot, before.as_deref(), ctx).await?;
+            before = archived
+                .threads
+                .iter()
+                .filter_map(|thread| thread.thread_metadata?.archive_timestamp)
+                .min()
+                .map(|timestamp| timestamp.to_string());
+            for thread in archived.threads {
+                threads.entry(thread.name).or_insert(thread.id);
+            }
+            if !archived.has_more || before.is_none() {
+                return Ok(threads);
+            }
+        }
+    }
+}
+
+pub async fn find_qa_thread(zitat_id: u64, ctx: &Context, guild: &Guild) -> Result<Option<ChannelId>> {
+    ThreadLookup::new(guild, true).find(zitat_id, ctx).await
+}
+
+pub async fn delete_qa_thread(zitat_id: u64, ctx: &Context, guild: &Guild) -> Result<()> {
+    let channel = match find_qa_thread(zitat_id, ctx, guild).await? {
+        Some(channel) => channel,
+        None => {
+            log(&format!("No thread found for Zitat with ID {zitat_id}"), "WARN");
+            return Ok(());
+        }
+    };
     let channel_id = *channel.as_u64();
-    ctx.http.delete_channel(channel_id).await.unwrap();
+    ctx.http.delete_channel(channel_id).await?;
     ctx.http
-        .delete_message(config.get("channelBot").expect("channelBot value not found in config file"), channel_id)
-        .await
-        .unwrap();
+        .delete_message(guild.channel_bot, channel_id)
+        .await?;
+    qa_thread::delete(zitat_id)?;
     log(
-        &format!("Deleted Thread for Zitat with ID {msg_id}"),
+        &format!("Deleted Thread for Zitat with ID {zitat_id}"),
         "INFO",
     );
+    Ok(())
 }
 
 pub async fn fetch_message_from_id(msg_id: u64, channel_id: u64, ctx: &Context) -> Option<Message> {
     if let Some(cache_result) = ctx.cache.message(channel_id, msg_id) {
         Some(cache_result)
-    } else if let Ok(http_result) = ctx.http.get_message(channel_id, msg_id).await {
-        Some(http_result)
     } else {
-        None
+        ctx.http.get_message(channel_id, msg_id).await.ok()
     }
 }
 
-pub async fn send_dm(id: u64, message: String, ctx: &Context) {
+const MAX_MESSAGE_LENGTH: usize = 2000;
+const ZITAT_SEPARATOR: &str = "\n------------------\n";
+
+// Splits an entry that is too long for one message, preferably at a line break
+fn split_entry(entry: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut rest = entry;
+    while rest.len() > MAX_MESSAGE_LENGTH {
+        let mut end = MAX_MESSAGE_LENGTH;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        if let Some(line_break) = rest[..end].rfind('\n').filter(|i| *i > 0) {
+            end = line_break;
+        }
+        parts.push(&rest[..end]);
+        rest = rest[end..].trim_start_matches('\n');
+    }
+    parts.push(rest);
+    parts
+}
+
+/// Packs the Zitate of `text` into as few messages as possible, each at most 2000 bytes long.
+pub fn split_message(text: String) -> Vec<String> {
+    if text.len() <= MAX_MESSAGE_LENGTH {
+        return vec![text];
+    }
+    let mut messages = Vec::new();
+    let mut current = String::new();
+    for part in text.split(ZITAT_SEPARATOR).flat_map(split_entry) {
+        if current.is_empty() {
+            current.push_str(part);
+        } else if current.len() + ZITAT_SEPARATOR.len() + part.len() <= MAX_MESSAGE_LENGTH {
+            current.push_str(ZITAT_SEPARATOR);
+            current.push_str(part);
+        } else {
+            messages.push(std::mem::replace(&mut current, part.to_string()));
+        }
+    }
+    if !current.is_empty() {
+        messages.push(current);
+    }
+    messages
+}
+
+pub async fn send_dm(id: u64, message: String, ctx: &Context) -> Result<()> {
     println!("Sending DM to {id}: {message}");
     if let Some(user) = ctx.cache.user(id) {
         user.direct_message(&ctx, |m| m.content(&message))
-            .await
-            .unwrap();
+            .await?;
     } else {
         ctx.http
             .get_user(id)
-            .await
-            .unwrap()
+            .await?
             .direct_message(&ctx, |m| m.content(&message))
-            .await
-            .unwrap();
+            .await?;
     }
+    Ok(())
 }
 
-pub async fn init_client(config: PmlStruct, ctx_producer: Arc<Mutex<Sender<Context>>>) -> Client {
+pub async fn init_client(config: SharedConfig, ctx_producers: Arc<Mutex<Vec<Sender<Context>>>>) -> Client {
     let intents = GatewayIntents::GUILDS
         | GatewayIntents::GUILD_MESSAGES
         | GatewayIntents::MESSAGE_CONTENT
         | GatewayIntents::DIRECT_MESSAGES;
-    let bot_token = config.get::<String>("botToken").expect("botToken value not found in config file");
+    let bot_token = config.get().bot_token.clone();
     Client::builder(bot_token, intents)
         .event_handler(Handler {
             config,
-            ctx_producer,
+            ctx_producers,
         })
         .await
         .expect("Error creating client")
 }
 
-pub async fn create_qa_thread(zitat_msg: &Message, config: &pml::PmlStruct, ctx: &Context) {
-    let channel_id = config.get("channelBot").expect("channelBot value not found in config file");
+pub async fn create_qa_thread(zitat_msg: &Message, guild: &Guild, ctx: &Context) -> Result<()> {
+    let channel_id = guild.channel_bot;
     let bot_channel = if let Some(GuildChannel(bot_channel)) = ctx.cache.channel(channel_id) {
         bot_channel
-    } else if let GuildChannel(bot_channel) = ctx.http.get_channel(channel_id).await.unwrap() {
+    } else if let GuildChannel(bot_channel) = ctx.http.get_channel(channel_id).await? {
         bot_channel
     } else {
-        log("Could not get #zitate-bot", "ERR ");
-        return;
+        return Err(Error::Config(format!("#zitate-bot ({channel_id}) of guild {} is not a server channel", guild.id)));
     };
     let thread_msg = bot_channel
         .say(
             &ctx.http,
-            format!("{}\n{}", zitat_msg.link(), zitat_msg.content),
+            format!("{}\n{}", message_link(guild.id, zitat_msg.channel_id.0, zitat_msg.id.0), zitat_msg.content),
             )
-        .await
-        .unwrap();
-    ChannelId(channel_id)
+        .await?;
+    let thread = ChannelId(channel_id)
         .create_public_thread(&ctx.http, thread_msg, |thread| {
             thread
                 .name(zitat_msg.id.as_u64().to_string())
                 .kind(ChannelType::PublicThread)
         })
-    .await
-        .unwrap();
+    .await?;
+    qa_thread::insert(zitat_msg.id.0, thread.id.0)?;
     log("Created thread in #zitate-bot", "INFO");
+    update_status_message(zitat_msg.id.0, thread.id, ctx).await
+}
+
+pub async fn update_status_message(zitat_id: u64, thread_id: ChannelId, ctx: &Context) -> Result<()> {
+    let text = get_attributions(zitat_id)?;
+    if let Some(message_id) = qa_thread::get_status_message(zitat_id)?
+        && thread_id.edit_message(&ctx.http, message_id, |m| m.content(&text)).await.is_ok()
+    {
+        return Ok(());
+    }
+    let message = thread_id.say(&ctx.http, &text).await?;
+    if let Err(why) = message.pin(&ctx.http).await {
+        log(&format!("Could not pin status message for Zitat with ID {zitat_id}: {why}"), "WARN");
+    }
+    qa_thread::set_status_message(zitat_id, message.id.0)?;
+    Ok(())
 }
 
 pub async fn set_status_based_on_start_parameter(ctx: &Context) {
@@ -108,3 +263,23 @@ pub async fn set_status_based_on_start_parameter(ctx: &Context) {
         ctx.set_activity(Activity::watching("#📃-zitate")).await;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_message_keeps_every_part_within_the_limit() {
+        let mut entries = vec!["a".repeat(300); 6];
+        entries.push("b".repeat(900));
+        entries.push(format!("{}\n{}", "c".repeat(1500), "ä".repeat(1200)));
+        let text = entries.join(ZITAT_SEPARATOR);
+
+        let messages = split_message(text.clone());
+
+        assert!(messages.iter().all(|message| !message.is_empty() && message.len() <= MAX_MESSAGE_LENGTH));
+        let joined: String = messages.concat().chars().filter(|c| "abcä".contains(*c)).collect();
+        let expected: String = text.chars().filter(|c| "abcä".contains(*c)).collect();
+        assert_eq!(joined, expected);
+    }
+}
@@ -0,0 +1,48 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    Db(sqlite::Error),
+    // Boxed because serenity's error is large and would bloat every Result
+    Http(Box<serenity::Error>),
+    Config(String),
+    // Reading or writing a file failed; the message names the file
+    File(String),
+    // Caused by what someone typed; the message is shown to them as is
+    UserInput(String),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    pub fn user_message(&self) -> String {
+        match self {
+            Error::UserInput(message) => message.clone(),
+            _ => String::from("Da ist leider etwas schiefgelaufen, bitte versuch es später nochmal."),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Db(why) => write!(f, "DB error: {why}"),
+            Error::Http(why) => write!(f, "Discord error: {why}"),
+            Error::Config(why) => write!(f, "Config error: {why}"),
+            Error::File(why) => write!(f, "File error: {why}"),
+            Error::UserInput(why) => write!(f, "{why}"),
+        }
+    }
+}
+
+impl From<sqlite::Error> for Error {
+    fn from(why: sqlite::Error) -> Self {
+        Error::Db(why)
+    }
+}
+
+impl From<serenity::Error> for Error {
+    fn from(why: serenity::Error) -> Self {
+        Error::Http(Box::new(why))
+    }
+}
@@ -1,6 +1,6 @@
 use serenity::{
     builder::{CreateApplicationCommand, CreateApplicationCommands},
-    model::prelude::command::CommandOptionType,
+    model::{prelude::command::CommandOptionType, Permissions},
 };
 
 pub fn create_all(commands: &mut CreateApplicationCommands) -> &mut CreateApplicationCommands {
@@ -8,8 +8,16 @@ pub fn create_all(commands: &mut CreateApplicationCommands) -> &mut CreateApplic
         .create_application_command(|command| stats(command))
         .create_application_command(|command| ranking(command))
         .create_application_command(|command| zitate(command))
+        .create_application_command(|command| suche(command))
+        .create_application_command(|command| zufall(command))
+        .create_application_command(|command| andiesemtag(command))
+        .create_application_command(|command| verlauf(command))
+        .create_application_command(|command| offen(command))
+        .create_application_command(|command| alias(command))
         .create_application_command(|command| gesagt(command))
         .create_application_command(|command| assistiert(command))
+        .create_application_command(|command| entfernen(command))
+        .create_application_command(|command| status(command))
         .create_application_command(|command| fertig(command))
 }
 
@@ -22,6 +30,13 @@ fn stats(command: &mut CreateApplicationCommand) -> &mut CreateApplicationComman
                 .name("name")
                 .description("Der, von dem du die Statistiken willst")
                 .kind(CommandOptionType::String)
+                .set_autocomplete(true)
+        })
+        .create_option(|option| {
+            option
+                .name("nutzer")
+                .description("Der Discord-Nutzer, von dem du die Statistiken willst")
+                .kind(CommandOptionType::User)
         })
 }
 
@@ -37,6 +52,24 @@ fn ranking(command: &mut CreateApplicationCommand) -> &mut CreateApplicationComm
                        .add_string_choice("gesagt", "said")
                        .add_string_choice("geschrieben", "wrote")
                        .add_string_choice("assistiert", "assisted"))
+        .create_option(|option| option
+                       .name("jahr")
+                       .description("Nur Zitate aus diesem Jahr")
+                       .kind(CommandOptionType::Integer))
+        .create_option(|option| option
+                       .name("monat")
+                       .description("Nur Zitate aus diesem Monat")
+                       .kind(CommandOptionType::Integer)
+                       .min_int_value(1)
+                       .max_int_value(12))
+        .create_option(|option| option
+                       .name("von")
+                       .description("Nur Zitate ab diesem Datum (TT.MM.JJJJ)")
+                       .kind(CommandOptionType::String))
+        .create_option(|option| option
+                       .name("bis")
+                       .description("Nur Zitate bis zu diesem Datum (TT.MM.JJJJ)")
+                       .kind(CommandOptionType::String))
 }
 
 fn zitate(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
@@ -48,6 +81,151 @@ fn zitate(command: &mut CreateApplicationCommand) -> &mut CreateApplicationComma
                 .name("name")
                 .description("Der, von dem du die Zitate willst")
                 .kind(CommandOptionType::String)
+                .set_autocomplete(true)
+        })
+        .create_option(|option| {
+            option
+                .name("nutzer")
+                .description("Der Discord-Nutzer, von dem du die Zitate willst")
+                .kind(CommandOptionType::User)
+        })
+}
+
+fn suche(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .name("suche")
+        .description("Durchsucht alle Zitate nach einem Text")
+        .create_option(|option| {
+            option
+                .name("text")
+                .description("Wonach du suchen willst")
+                .kind(CommandOptionType::String)
+                .required(true)
+        })
+        .create_option(|option| {
+            option
+                .name("gesagt")
+                .description("Nur Zitate, die diese Person gesagt hat")
+                .kind(CommandOptionType::String)
+        })
+        .create_option(|option| {
+            option
+                .name("geschrieben")
+                .description("Nur Zitate, die diese Person geschrieben hat")
+                .kind(CommandOptionType::String)
+        })
+        .create_option(|option| {
+            option
+                .name("von")
+                .description("Nur Zitate ab diesem Datum (TT.MM.JJJJ)")
+                .kind(CommandOptionType::String)
+        })
+        .create_option(|option| {
+            option
+                .name("bis")
+                .description("Nur Zitate bis zu diesem Datum (TT.MM.JJJJ)")
+                .kind(CommandOptionType::String)
+        })
+}
+
+fn zufall(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .name("zufall")
+        .description("Zeigt ein zufälliges Zitat an")
+        .create_option(|option| {
+            option
+                .name("gesagt")
+                .description("Nur Zitate, die diese Person gesagt hat")
+                .kind(CommandOptionType::String)
+        })
+        .create_option(|option| {
+            option
+                .name("geschrieben")
+                .description("Nur Zitate, die diese Person geschrieben hat")
+                .kind(CommandOptionType::String)
+        })
+        .create_option(|option| {
+            option
+                .name("jahr")
+                .description("Nur Zitate aus diesem Jahr")
+                .kind(CommandOptionType::Integer)
+        })
+}
+
+fn andiesemtag(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .name("andiesemtag")
+        .description("Zeigt die Zitate, die in früheren Jahren an diesem Tag geschrieben wurden")
+}
+
+fn offen(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .name("offen")
+        .description("Zeigt alle Zitate, bei denen noch niemand eingetragen ist, der sie gesagt hat")
+}
+
+fn verlauf(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .name("verlauf")
+        .description("Zeigt alle früheren Versionen eines bearbeiteten Zitats an")
+        .create_option(|option| {
+            option
+                .name("id")
+                .description("Die Nachrichten-ID des Zitats (im Thread eines Zitats nicht nötig)")
+                .kind(CommandOptionType::String)
+        })
+}
+
+fn alias(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .name("alias")
+        .description("Verwaltet Zweitaccounts von Nutzern")
+        .default_member_permissions(Permissions::ADMINISTRATOR)
+        .create_option(|option| {
+            option
+                .name("verknüpfen")
+                .description("Verknüpft einen Zweitaccount mit einem Nutzer")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|option| {
+                    option
+                        .name("name")
+                        .description("Der Nutzer, zu dem der Zweitaccount gehört")
+                        .kind(CommandOptionType::String)
+                        .required(true)
+                })
+                .create_sub_option(|option| {
+                    option
+                        .name("id")
+                        .description("Die Discord-ID des Zweitaccounts")
+                        .kind(CommandOptionType::String)
+                        .required(true)
+                })
+        })
+        .create_option(|option| {
+            option
+                .name("entfernen")
+                .description("Entfernt die Verknüpfung eines Zweitaccounts")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|option| {
+                    option
+                        .name("id")
+                        .description("Die Discord-ID des Zweitaccounts")
+                        .kind(CommandOptionType::String)
+                        .required(true)
+                })
+        })
+        .create_option(|option| {
+            option
+                .name("anzeigen")
+                .description("Zeigt alle Zweitaccounts eines Nutzers an")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|option| {
+                    option
+                        .name("name")
+                        .description("Der Nutzer, dessen Zweitaccounts du sehen willst")
+                        .kind(CommandOptionType::String)
+                        .required(true)
+                })
         })
 }
 
@@ -58,9 +236,15 @@ fn gesagt(command: &mut CreateApplicationCommand) -> &mut CreateApplicationComma
         .create_option(|option| {
             option
                 .name("name")
-                .description("Der, der das Zitat gesagt hat")
+                .description("Die, die das Zitat gesagt haben (mehrere mit Komma trennen)")
                 .kind(CommandOptionType::String)
-                .required(true)
+                .set_autocomplete(true)
+        })
+        .create_option(|option| {
+            option
+                .name("nutzer")
+                .description("Der Discord-Nutzer, der das Zitat gesagt hat")
+                .kind(CommandOptionType::User)
         })
 }
 
@@ -71,10 +255,50 @@ fn assistiert(command: &mut CreateApplicationCommand) -> &mut CreateApplicationC
         .create_option(|option| {
             option
                 .name("name")
-                .description("Der, der einen Assist gemacht hat")
+                .description("Die, die einen Assist gemacht haben (mehrere mit Komma trennen)")
+                .kind(CommandOptionType::String)
+                .set_autocomplete(true)
+        })
+        .create_option(|option| {
+            option
+                .name("nutzer")
+                .description("Der Discord-Nutzer, der einen Assist gemacht hat")
+                .kind(CommandOptionType::User)
+        })
+}
+
+fn entfernen(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .name("entfernen")
+        .description("Entfernt einen falsch eingetragenen Zitierten oder Assister vom Zitat")
+        .create_option(|option| {
+            option
+                .name("kategorie")
+                .description("Ob der Eintrag als gesagt oder assistiert entfernt werden soll")
                 .kind(CommandOptionType::String)
                 .required(true)
+                .add_string_choice("gesagt", "said")
+                .add_string_choice("assistiert", "assisted")
+        })
+        .create_option(|option| {
+            option
+                .name("name")
+                .description("Die, die entfernt werden sollen (mehrere mit Komma trennen)")
+                .kind(CommandOptionType::String)
+                .set_autocomplete(true)
         })
+        .create_option(|option| {
+            option
+                .name("nutzer")
+                .description("Der Discord-Nutzer, der entfernt werden soll")
+                .kind(CommandOptionType::User)
+        })
+}
+
+fn status(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .name("status")
+        .description("Zeigt, wer für das Zitat bereits als Zitierter oder Assister eingetragen ist")
 }
 
 fn fertig(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
@@ -1,30 +1,41 @@
 mod create_commands;
 use crate::{
-    db::{add_qa, get_ranking, new_connection, user},
-    discord::{send_dm, delete_qa_thread, set_status_based_on_start_parameter},
+    config::{Config, SharedConfig},
+    db::{User, add_qa, guild, get_attributions, get_open_zitate, qa_thread, daily::get_anniversary_zitate, get_random_zitat, get_ranking, get_zitat_history, new_connection, remove_qa, search_zitate, update_zitat_text, user, ZitatFilter, TimeWindow},
+    discord::{send_dm, delete_qa_thread, set_status_based_on_start_parameter, split_message, update_status_message},
+    error::{Error, Result},
     logging::log,
+    mark_zitat_deleted,
     register_zitat,
-    remove_zitat,
+    sync,
     QAType,
     RankingType
 };
 use std::sync::{mpsc, Arc, Mutex};
 
+use chrono::{Datelike, Local};
+
 use serenity::{
     async_trait,
     model::{
-        application::interaction::Interaction,
-        channel::{Channel, Message},
+        application::interaction::{
+            application_command::{ApplicationCommandInteraction, CommandDataOption, CommandDataOptionValue},
+            autocomplete::AutocompleteInteraction,
+            Interaction,
+        },
+        channel::{Channel, GuildChannel, Message},
         gateway::Ready,
         id::{ChannelId, GuildId, MessageId, UserId as SerenityUserId},
+        user::User as SerenityUser,
         prelude::{MessageType, MessageUpdateEvent},
+        timestamp::Timestamp,
     },
     prelude::{Context, EventHandler}
 };
 
 pub struct Handler {
-    pub config: pml::PmlStruct,
-    pub ctx_producer: Arc<Mutex<mpsc::Sender<Context>>>,
+    pub config: SharedConfig,
+    pub ctx_producers: Arc<Mutex<Vec<mpsc::Sender<Context>>>>,
 }
 
 #[async_trait]
@@ -32,22 +43,31 @@ impl EventHandler for Handler {
     async fn ready(&self, ctx: Context, _: Ready) {
         log("Logged in", "INFO");
         set_status_based_on_start_parameter(&ctx).await;
-        GuildId(self.config.get("guildId").expect("guildId value not found in config file"))
-            .set_application_commands(&ctx.http, |commands| create_commands::create_all(commands))
-            .await
-            .unwrap();
-        self.ctx_producer.lock().unwrap().send(ctx).unwrap();
+        let guilds = guild::get_all().unwrap_or_else(|why| {
+            log(&format!("Could not load guilds: {why}"), "ERR ");
+            Vec::new()
+        });
+        for guild in &guilds {
+            register_commands(guild.id, &ctx).await;
+        }
+        for ctx_producer in self.ctx_producers.lock().unwrap().iter() {
+            let _ = ctx_producer.send(ctx.clone());
+        }
+        for guild in &guilds {
+            match sync::run(guild, &ctx, false, false).await {
+                Ok(report) => log(&report, "INFO"),
+                Err(why) => log(&format!("Sync of guild {} failed: {why}", guild.id), "ERR "),
+            }
+        }
     }
 
     async fn message(&self, ctx: Context, msg: Message) {
-        let config = &self.config;
-        let zitate_channel_id = config.get::<u64>("channelZitate").expect("channelZitate value not found in config file");
         if msg.author.bot || msg.kind != MessageType::Regular {
             return;
-        } else if *msg.channel_id.as_u64() == zitate_channel_id {
-            register_zitat(msg, config, &ctx).await;
-        } else if let Channel::Private(_) = msg.channel(&ctx).await.unwrap() {
-            dm_handler(msg, config, &ctx).await;
+        }
+        let msg_id = msg.id.0;
+        if let Err(why) = handle_message(msg, &self.config, &ctx).await {
+            log(&format!("Could not handle message with ID {msg_id}: {why}"), "ERR ");
         }
     }
 
@@ -58,9 +78,13 @@ impl EventHandler for Handler {
         msg_id: MessageId,
         _: Option<GuildId>,
     ) {
-        let config = &self.config;
-        if channel_id.0 == config.get::<u64>("channelZitate").expect("channelZitate value not found in config file") {
-            remove_zitat(msg_id.0, &ctx, config).await;
+        let result = match guild::get_by_zitate_channel(channel_id.0) {
+            Ok(Some(guild)) => mark_zitat_deleted(msg_id.0, &ctx, &guild).await,
+            Ok(None) => Ok(()),
+            Err(why) => Err(why),
+        };
+        if let Err(why) = result {
+            log(&format!("Could not mark Zitat with ID {} as deleted: {why}", msg_id.0), "ERR ");
         }
     }
 
@@ -71,212 +95,426 @@ impl EventHandler for Handler {
         _: Option<Message>,
         event: MessageUpdateEvent,
     ) {
-        if *event.channel_id.as_u64() != self.config.get::<u64>("channelZitate").expect("channelZitate value not found in config file") {
-            return;
+        let msg_id = event.id.0;
+        if let Err(why) = handle_message_update(event) {
+            log(&format!("Could not update Zitat with ID {msg_id}: {why}"), "ERR ");
         }
-        let zitat_id = event.id.0 as i64;
-        let new_text = event.content.unwrap();
-
-        let connection = new_connection();
-
-        let mut statement = connection.prepare("SELECT text FROM zitat WHERE id = :id").unwrap();
-        let _ = statement.bind((":id", zitat_id));
-        let _ = statement.next();
-        let old_text = statement.read::<String, _>(0).unwrap();
+    }
 
-        if old_text == old_text {
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        if let Interaction::Autocomplete(autocomplete) = &interaction {
+            autocomplete_handler(autocomplete, &ctx).await;
             return;
         }
-        log(
-            &format!("Changing content of Zitat with ID {zitat_id}:"),
-            "INFO",
-        );
-        log(&old_text, "INFO");
-        log("->", "INFO");
-        log(&new_text, "INFO");
+        if let Interaction::ApplicationCommand(command) = interaction {
+            let (response_text, ephemeral) = match handle_command(&command, &ctx).await {
+                Ok(Some(response_text)) => (response_text, false),
+                Ok(None) => return,
+                Err(why) => {
+                    if !matches!(why, Error::UserInput(_)) {
+                        log(
+                            &format!(
+                                "/{} by {} in channel {} failed: {why}",
+                                command.data.name,
+                                command.user.tag(),
+                                command.channel_id.0
+                            ),
+                            "ERR ",
+                        );
+                    }
+                    (why.user_message(), true)
+                }
+            };
+            if let Err(why) = respond(&command, &ctx, response_text, ephemeral).await {
+                log(&format!("Could not respond to /{}: {why}", command.data.name), "ERR ");
+            }
+        }
+    }
+}
 
-        let mut statement = connection.prepare("UPDATE zitate SET text = :text WHERE id = :id").unwrap();
-        let _ = statement.bind((":text", AsRef::<str>::as_ref(&new_text)));
-        let _ = statement.bind((":id", zitat_id));
-        let _ = statement.next();
+async fn handle_message(msg: Message, config: &SharedConfig, ctx: &Context) -> Result<()> {
+    if let Some(guild) = guild::get_by_zitate_channel(msg.channel_id.0)? {
+        register_zitat(msg, &guild, ctx).await
+    } else if let Channel::Private(_) = msg.channel(ctx).await? {
+        dm_handler(msg, &config.get(), ctx).await
+    } else {
+        Ok(())
+    }
+}
 
-        log("Zitat successfully updated", "INFO");
+fn handle_message_update(event: MessageUpdateEvent) -> Result<()> {
+    if guild::get_by_zitate_channel(event.channel_id.0)?.is_none() {
+        return Ok(());
     }
+    if let Some(new_text) = event.content {
+        let edited_at = event.edited_timestamp.unwrap_or_else(Timestamp::now);
+        update_zitat_text(event.id.0, new_text.trim(), &edited_at.to_rfc3339())?;
+    }
+    Ok(())
+}
 
-    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
-        if let Interaction::ApplicationCommand(command) = interaction {
-            let channel_id = *command.channel_id.as_u64();
-            let parent_id = match command.channel_id.to_channel(&ctx).await.unwrap() {
-                Channel::Guild(channel) => channel.parent_id.unwrap().0,
-                _ => return,
+// QA threads are named after the ID of their Zitat
+fn get_thread_zitat_id(channel: &GuildChannel) -> Result<u64> {
+    channel
+        .name
+        .parse::<u64>()
+        .map_err(|_| Error::UserInput(String::from("Dieser Thread gehört zu keinem Zitat")))
+}
+
+async fn handle_command(command: &ApplicationCommandInteraction, ctx: &Context) -> Result<Option<String>> {
+    let channel_id = *command.channel_id.as_u64();
+    let channel = match command.channel_id.to_channel(ctx).await? {
+        Channel::Guild(channel) => channel,
+        _ => return Ok(None),
+    };
+    let guild = match command.guild_id {
+        Some(guild_id) => guild::get(guild_id.0)?
+            .ok_or_else(|| Error::UserInput(String::from("Der Bot ist für diesen Server nicht eingerichtet")))?,
+        None => return Ok(None),
+    };
+    let bot_channel_id = guild.channel_bot;
+    let in_qa_thread = channel.parent_id.map(|id| id.0) == Some(bot_channel_id);
+    let response_text = match command.data.name.as_str() {
+        "stats" if channel_id == bot_channel_id => {
+            let user = match resolve_user(&command.data.options, guild.id, ctx).await? {
+                Some(user) => user,
+                None => user::get(&command.user.id.0)?
+                    .ok_or_else(|| Error::UserInput(String::from("User not found")))?,
             };
-            let channel = match command.channel_id.to_channel(&ctx).await.unwrap() {
-                Channel::Guild(channel) => channel,
-                _ => return,
+            user::get_stats(user, guild.id)?
+        }
+        "ranking" if channel_id == bot_channel_id => {
+            let r#type = match get_option_str(&command.data.options, "kategorie") {
+                Some("said") => RankingType::Said,
+                Some("wrote") => RankingType::Wrote,
+                Some("assisted") => RankingType::Assisted,
+                _ => return Err(Error::UserInput(String::from("Unbekannte Kategorie"))),
             };
-            let bot_channel_id = self.config.get::<u64>("channelBot").expect("channelBot value not found in config file");
-            let response_text = match command.data.name.as_str() {
-                "stats" if channel_id == bot_channel_id => {
-                    let user = match command.data.options.get(0) {
-                        Some(input) => {
-                            let input = input.value.as_ref().unwrap().as_str().unwrap();
-                            let len = input.len()-1;
-                            if input.starts_with("<@") && input.ends_with('>') {
-                                let id = input[2..len].parse::<u64>().unwrap();
-                                user::get(&id)
-                            } else {
-                                user::get(&input.to_string())
-                            }
-                        }
-                        None => user::get(&command.user.id.0)
-                    };
-                    match user {
-                        Some(user) => user::get_stats(user),
-                        None => String::from("User not found"),
-                    }
-                }
-                "ranking" if channel_id == bot_channel_id => {
-                    let r#type = match command
-                        .data
-                        .options
-                        .get(0)
-                        .unwrap()
-                        .value
-                        .as_ref()
-                        .unwrap()
-                        .as_str()
-                        .unwrap()
-                    {
-                        "said" => RankingType::Said,
-                        "wrote" => RankingType::Wrote,
-                        "assisted" => RankingType::Assisted,
-                        _ => return,
-                    };
-                    get_ranking(r#type)
-                }
-                "zitate" if channel_id == bot_channel_id => {
-                    let user = match command.data.options.get(0) {
-                        Some(input) => {
-                            let input = input.value.as_ref().unwrap().as_str().unwrap();
-                            let len = input.len()-1;
-                            if input.starts_with("<@") && input.ends_with('>') {
-                                let id = input[2..len].parse::<u64>().unwrap();
-                                user::get(&id)
-                            } else {
-                                user::get(&input.to_string())
-                            }
-                        }
-                        None => user::get(&command.user.id.0)
-                    };
-                    match user {
-                        Some(user) => user::get_zitate(user),
-                        None => String::from("User not found"),
-                    }
-                }
-                "gesagt" if parent_id == bot_channel_id => {
-                    let zitat_id = channel.name.parse::<u64>().unwrap();
-                    let input = command.data.options.get(0).unwrap().value.as_ref().unwrap().as_str().unwrap();
-                    let len = input.len()-1;
-                    match
-                        if input.starts_with("<@") && input.ends_with('>') {
-                            let id = input[2..len].parse::<u64>().unwrap();
-                            user::get(&id)
-                        } else {
-                            user::get(&input.to_string())
-                        }
-                    {
-                        Some(user) => add_qa(QAType::Said, user, zitat_id),
-                        None => String::from("User not found"),
-                    }
-                }
-                "assistiert" if parent_id == bot_channel_id => {
-                    let zitat_id = channel.name.parse::<u64>().unwrap();
-                    let input = command.data.options.get(0).unwrap().value.as_ref().unwrap().as_str().unwrap();
-                    let len = input.len()-1;
-                    match
-                        if input.starts_with("<@") && input.ends_with('>') {
-                            let id = input[2..len].parse::<u64>().unwrap();
-                            user::get(&id)
-                        } else {
-                            user::get(&input.to_string())
-                        }
-                    {
-                        Some(user) => add_qa(QAType::Assisted, user, zitat_id),
-                        None => String::from("User not found"),
-                    }
-                }
-                "fertig" if parent_id == bot_channel_id => {
-                    let zitat_id = channel.name.parse::<u64>().unwrap();
-                    let said_exists = {
-                        let connection = new_connection();
-                        let mut statement = connection.prepare("SELECT count(*) FROM said WHERE zitat = :id").unwrap();
-                        let _ = statement.bind((":id", zitat_id as i64));
-                        let _ = statement.next();
-                        statement.read::<i64, _>(0).unwrap() > 0
-                    };
-                    if said_exists {
-                        let thread_name = match command.channel_id.to_channel(&ctx).await.unwrap() {
-                            Channel::Guild(channel) => channel.name,
-                            _ => return,
-                        };
-                        delete_qa_thread(thread_name, &ctx, &self.config).await;
-                        return;
-                    } else {
-                        String::from("Nein, bist du nicht")
-                    }
-                }
-                _ => return,
+            let window = get_time_window(&command.data.options)?;
+            get_ranking(r#type, guild.id, &window)?
+        }
+        "zitate" if channel_id == bot_channel_id => {
+            let user = match resolve_user(&command.data.options, guild.id, ctx).await? {
+                Some(user) => user,
+                None => user::get(&command.user.id.0)?
+                    .ok_or_else(|| Error::UserInput(String::from("User not found")))?,
+            };
+            user::get_zitate(user, guild.id)?
+        }
+        "suche" if channel_id == bot_channel_id => {
+            let query = get_option_str(&command.data.options, "text").unwrap_or_default();
+            let filter = get_zitat_filter(guild.id, &command.data.options)?;
+            search_zitate(query, filter)?
+        }
+        "zufall" if channel_id == bot_channel_id => {
+            get_random_zitat(get_zitat_filter(guild.id, &command.data.options)?)?
+        }
+        "andiesemtag" if channel_id == bot_channel_id => {
+            get_anniversary_zitate(guild.id, Local::now().date_naive())?
+                .unwrap_or_else(|| String::from("An diesem Tag gibt es noch keine Zitate"))
+        }
+        "offen" if channel_id == bot_channel_id => get_open_zitate(guild.id)?,
+        "verlauf" if channel_id == bot_channel_id || in_qa_thread => {
+            let zitat_id = match get_option_str(&command.data.options, "id") {
+                Some(input) => input.trim().parse::<u64>().ok(),
+                None if in_qa_thread => channel.name.parse::<u64>().ok(),
+                None => None,
             };
-            let (response_1, rest) = if response_text.len() <= 2000 {
-                (response_text, Vec::new())
+            match zitat_id {
+                Some(id) if guild::get_for_zitat(id)?.is_some_and(|zitat_guild| zitat_guild.id == guild.id) => get_zitat_history(id)?,
+                Some(_) => String::from("Zitat nicht gefunden"),
+                None => return Err(Error::UserInput(String::from("Ungültige Zitat-ID"))),
             }
-            else {
-                let indices: Vec<usize> = response_text
-                    .match_indices("\n------------------\n")
-                    .map(|(i, _)| i)
-                    .collect();
-                let mut responses = Vec::new();
-                let mut last_break = 0;
-                let mut previous = 0;
-                for current in &indices {
-                    if *current > last_break + 2000 {
-                        responses.push(response_text[last_break..previous].to_string());
-                        last_break = previous;
-                    }
-                    previous = *current;
-                }
-                if indices.len() > last_break + 2000 {
-                    responses.push(response_text[last_break..previous].to_string());
-                    last_break = previous;
-                }
-                responses.push(response_text[last_break..].to_string());
-                (responses.remove(0), responses)
+        }
+        "alias" if channel_id == bot_channel_id => {
+            let is_admin = command.member
+                .as_ref()
+                .and_then(|member| member.permissions)
+                .is_some_and(|permissions| permissions.administrator());
+            if !is_admin {
+                return Err(Error::UserInput(String::from("Das dürfen nur Admins")));
+            }
+            let subcommand = match command.data.options.first() {
+                Some(subcommand) => subcommand,
+                None => return Ok(None),
+            };
+            let secondary_id = get_option_str(&subcommand.options, "id").map(|id| id.trim().parse::<u64>());
+            let user = match get_option_str(&subcommand.options, "name") {
+                Some(name) => user::get_in_guild(name, guild.id)?,
+                None => None,
+            };
+            match (subcommand.name.as_str(), user, secondary_id) {
+                ("verknüpfen", Some(user), Some(Ok(id))) => user::link(user, id)?,
+                ("entfernen", _, Some(Ok(id))) => user::unlink(id)?,
+                ("anzeigen", Some(user), _) => user::get_aliases(user)?,
+                (_, _, Some(Err(_))) => return Err(Error::UserInput(String::from("Ungültige ID"))),
+                _ => return Err(Error::UserInput(String::from("User not found"))),
+            }
+        }
+        "gesagt" | "assistiert" if in_qa_thread => {
+            let zitat_id = get_thread_zitat_id(&channel)?;
+            let r#type = if command.data.name == "gesagt" {
+                QAType::Said
+            } else {
+                QAType::Assisted
+            };
+            let users = resolve_users(&command.data.options, guild.id, ctx).await?;
+            if users.is_empty() {
+                return Err(Error::UserInput(String::from("Du musst einen Namen oder Nutzer angeben")));
+            }
+            let response = add_qa(r#type, users, zitat_id)?;
+            qa_thread::insert(zitat_id, channel_id)?;
+            update_status_message(zitat_id, command.channel_id, ctx).await?;
+            response
+        }
+        "entfernen" if in_qa_thread => {
+            let zitat_id = get_thread_zitat_id(&channel)?;
+            let r#type = match get_option_str(&command.data.options, "kategorie") {
+                Some("said") => QAType::Said,
+                Some("assisted") => QAType::Assisted,
+                _ => return Err(Error::UserInput(String::from("Unbekannte Kategorie"))),
+            };
+            let users = resolve_users(&command.data.options, guild.id, ctx).await?;
+            if users.is_empty() {
+                return Err(Error::UserInput(String::from("Du musst einen Namen oder Nutzer angeben")));
+            }
+            let response = remove_qa(r#type, users, zitat_id)?;
+            qa_thread::insert(zitat_id, channel_id)?;
+            update_status_message(zitat_id, command.channel_id, ctx).await?;
+            response
+        }
+        "status" if in_qa_thread => get_attributions(get_thread_zitat_id(&channel)?)?,
+        "fertig" if in_qa_thread => {
+            let zitat_id = get_thread_zitat_id(&channel)?;
+            let said_exists = {
+                let connection = new_connection()?;
+                let mut statement = connection.prepare("SELECT count(*) FROM said WHERE zitat = :id")?;
+                statement.bind((":id", zitat_id as i64))?;
+                statement.next()?;
+                statement.read::<i64, _>(0)? > 0
+            };
+            if !said_exists {
+                return Err(Error::UserInput(String::from("Nein, bist du nicht")));
+            }
+            delete_qa_thread(zitat_id, ctx, &guild).await?;
+            return Ok(None);
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(response_text))
+}
+
+pub async fn register_commands(guild_id: u64, ctx: &Context) {
+    if let Err(why) = GuildId(guild_id)
+        .set_application_commands(&ctx.http, |commands| create_commands::create_all(commands))
+        .await
+    {
+        log(&format!("Could not register commands in guild {guild_id}: {why}"), "ERR ");
+    }
+}
+
+async fn respond(command: &ApplicationCommandInteraction, ctx: &Context, response_text: String, ephemeral: bool) -> Result<()> {
+    let mut rest = split_message(response_text);
+    let response_1 = rest.remove(0);
+    command
+        .create_interaction_response(&ctx.http, |response| {
+            response.interaction_response_data(|message| message.content(response_1).ephemeral(ephemeral))
+        })
+        .await?;
+    for response in rest {
+        command
+            .create_followup_message(&ctx.http, |message| 
+                message.content(response).ephemeral(ephemeral)
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+fn get_option_str<'a>(options: &'a [CommandDataOption], name: &str) -> Option<&'a str> {
+    options
+        .iter()
+        .find(|option| option.name == name)
+        .and_then(|option| option.value.as_ref())
+        .and_then(|value| value.as_str())
+}
+
+fn parse_mention(input: &str) -> Option<u64> {
+    let id = input.trim().strip_prefix("<@")?.strip_suffix('>')?;
+    id.strip_prefix('!').unwrap_or(id).parse::<u64>().ok()
+}
+
+// Mentions are entries of their own, the text around them is kept as a name so nothing is dropped silently
+fn parse_user_list(input: &str) -> Vec<String> {
+    let mut entries = Vec::new();
+    let mut push_name = |name: &str| {
+        let name = name.trim();
+        if !name.is_empty() {
+            entries.push(name.to_string());
+        }
+    };
+    for part in input.split(',') {
+        let mut rest = part;
+        while let Some(start) = rest.find("<@") {
+            let end = match rest[start..].find('>') {
+                Some(end) => start + end + 1,
+                None => break,
             };
-            command
-                .create_interaction_response(&ctx.http, |response| {
-                    response.interaction_response_data(|message| message.content(response_1))
-                })
-                .await
-                .unwrap();
-            for response in rest {
-                command
-                    .create_followup_message(&ctx.http, |message| 
-                        message.content(response)
-                    )
-                    .await
-                    .unwrap();
+            push_name(&rest[..start]);
+            push_name(&rest[start..end]);
+            rest = &rest[end..];
+        }
+        push_name(rest);
+    }
+    entries
+}
+
+// The inner error is meant for the user and names the entry that could not be resolved
+fn register_discord_user(discord_user: &SerenityUser) -> Result<Result<User, String>> {
+    if discord_user.bot {
+        return Ok(Err(format!("{}: Bots können keine Zitate sagen", discord_user.name)));
+    }
+    Ok(Ok(user::get_or_add(discord_user.id.0, &discord_user.name)?))
+}
+
+async fn resolve_name(input: &str, guild: u64, ctx: &Context) -> Result<Result<User, String>> {
+    if input.starts_with("<@&") {
+        return Ok(Err(format!("{input}: Rollen können keine Zitate sagen")));
+    }
+    match parse_mention(input) {
+        Some(id) => match ctx.http.get_user(id).await {
+            Ok(discord_user) => register_discord_user(&discord_user),
+            Err(_) => Ok(Err(format!("{input}: User not found"))),
+        },
+        None => Ok(user::get_in_guild(input, guild)?.ok_or_else(|| format!("{input}: User not found"))),
+    }
+}
+
+fn get_option_user(options: &[CommandDataOption]) -> Option<&SerenityUser> {
+    match options.iter().find(|option| option.name == "nutzer").and_then(|option| option.resolved.as_ref()) {
+        Some(CommandDataOptionValue::User(discord_user, _)) => Some(discord_user),
+        _ => None,
+    }
+}
+
+async fn resolve_user(options: &[CommandDataOption], guild: u64, ctx: &Context) -> Result<Option<User>> {
+    let user = if let Some(discord_user) = get_option_user(options) {
+        register_discord_user(discord_user)?
+    } else {
+        match get_option_str(options, "name") {
+            Some(input) => resolve_name(input.trim(), guild, ctx).await?,
+            None => return Ok(None),
+        }
+    };
+    user.map(Some).map_err(Error::UserInput)
+}
+
+async fn resolve_users(options: &[CommandDataOption], guild: u64, ctx: &Context) -> Result<Vec<Result<User, String>>> {
+    let mut users = Vec::new();
+    if let Some(input) = get_option_str(options, "name") {
+        for entry in parse_user_list(input) {
+            users.push(resolve_name(&entry, guild, ctx).await?);
+        }
+    }
+    if let Some(discord_user) = get_option_user(options) {
+        users.push(register_discord_user(discord_user)?);
+    }
+    Ok(users)
+}
+
+fn get_option_i64(options: &[CommandDataOption], name: &str) -> Option<i64> {
+    options
+        .iter()
+        .find(|option| option.name == name)
+        .and_then(|option| option.value.as_ref())
+        .and_then(|value| value.as_i64())
+}
+
+fn get_time_window(options: &[CommandDataOption]) -> Result<TimeWindow> {
+    let year = get_option_i64(options, "jahr");
+    let month = get_option_i64(options, "monat");
+    if year.is_some() || month.is_some() {
+        if get_option_str(options, "von").is_some() || get_option_str(options, "bis").is_some() {
+            return Err(Error::UserInput(String::from("Du kannst nicht gleichzeitig Jahr/Monat und von/bis angeben")));
+        }
+        let year = year.unwrap_or_else(|| Local::now().year() as i64);
+        let window = match month {
+            Some(month) => TimeWindow::month(year as i32, month as u32),
+            None => TimeWindow::year(year as i32),
+        };
+        return window.ok_or_else(|| Error::UserInput(String::from("Ungültiger Zeitraum")));
+    }
+
+    let mut window = TimeWindow::default();
+    for (name, bound) in [("von", &mut window.from), ("bis", &mut window.to)] {
+        if let Some(input) = get_option_str(options, name) {
+            match TimeWindow::parse_date(input) {
+                Some(date) => *bound = Some(date),
+                None => return Err(Error::UserInput(format!("Ungültiges Datum \"{input}\" (Format: TT.MM.JJJJ)"))),
+            }
+        }
+    }
+    Ok(window)
+}
+
+fn get_zitat_filter(guild: u64, options: &[CommandDataOption]) -> Result<ZitatFilter> {
+    let mut filter = ZitatFilter {
+        guild,
+        window: get_time_window(options)?,
+        ..Default::default()
+    };
+    for (name, target) in [("gesagt", &mut filter.said), ("geschrieben", &mut filter.writer)] {
+        if let Some(input) = get_option_str(options, name) {
+            match user::get_id(&input.to_string())? {
+                Some(id) => *target = Some(id),
+                None => return Err(Error::UserInput(String::from("User not found"))),
             }
         }
     }
+    Ok(filter)
 }
 
-async fn dm_handler(msg: Message, config: &pml::PmlStruct, ctx: &Context) {
+async fn autocomplete_handler(autocomplete: &AutocompleteInteraction, ctx: &Context) {
+    let input = match autocomplete.data.options.iter().find(|option| option.focused) {
+        Some(option) => option.value.as_ref().and_then(|value| value.as_str()).unwrap_or_default(),
+        None => return,
+    };
+    let (previous_entries, current_entry) = match input.rfind(',') {
+        Some(i) => (&input[..=i], &input[i + 1..]),
+        None => ("", input),
+    };
+    let Some(guild_id) = autocomplete.guild_id else {
+        return;
+    };
+    let names = user::get_name_suggestions(current_entry, guild_id.0).unwrap_or_else(|why| {
+        log(&format!("Could not load name suggestions: {why}"), "ERR ");
+        Vec::new()
+    });
+    if let Err(why) = autocomplete
+        .create_autocomplete_response(&ctx.http, |response| {
+            for name in names {
+                let value = format!("{previous_entries} {name}");
+                // Discord rejects the whole response if a choice is longer than 100 characters
+                if value.trim().chars().count() > 100 {
+                    continue;
+                }
+                response.add_string_choice(value.trim(), value.trim());
+            }
+            response
+        })
+        .await
+    {
+        log(&format!("Could not send autocomplete response: {why}"), "ERR ");
+    }
+}
+
+async fn dm_handler(msg: Message, config: &Config, ctx: &Context) -> Result<()> {
     let SerenityUserId(author_id) = msg.author.id;
-    let owner_id = config.get("ownerId").expect("ownerId value not found in config file");
+    let owner_id = config.owner_id;
     if author_id == owner_id {
-        return;
+        return Ok(());
     }
-    let author = match user::get(&author_id) {
+    let author = match user::get(&author_id)? {
         Some(user_data) => user_data.name.to_string(),
         None => format!("{} (ID: {author_id})", msg.author.tag()),
     };
@@ -286,5 +524,27 @@ async fn dm_handler(msg: Message, config: &pml::PmlStruct, ctx: &Context) {
         format!("DM von {author}:\n{}", msg.content),
         ctx,
     )
-    .await;
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_mention_accepts_user_mentions_only() {
+        assert_eq!(parse_mention("<@123>"), Some(123));
+        assert_eq!(parse_mention(" <@!456> "), Some(456));
+        assert_eq!(parse_mention("<@&789>"), None);
+        assert_eq!(parse_mention("<@abc>"), None);
+        assert_eq!(parse_mention("Alice"), None);
+    }
+
+    #[test]
+    fn parse_user_list_keeps_names_next_to_mentions() {
+        assert_eq!(parse_user_list("Alice, Bob Marley"), ["Alice", "Bob Marley"]);
+        assert_eq!(parse_user_list("<@1><@!2>, Carl"), ["<@1>", "<@!2>", "Carl"]);
+        assert_eq!(parse_user_list("Alice <@1> Bob"), ["Alice", "<@1>", "Bob"]);
+        assert_eq!(parse_user_list(" , <@3 , "), ["<@3"]);
+    }
 }
@@ -14,7 +14,20 @@ fn get_log_file_path() -> String {
         .duration_since(UNIX_EPOCH)
         .unwrap()
         .as_millis());
-    format!("logs/{}.log", start_time)
+    format!("{}/{}.log", log_dir(), start_time)
+}
+
+#[cfg(not(test))]
+fn log_dir() -> String {
+    String::from("logs")
+}
+
+// Tests must not write into the logs of the bot
+#[cfg(test)]
+fn log_dir() -> String {
+    let dir = std::env::temp_dir().join("zitate_bot_test_logs");
+    fs::create_dir_all(&dir).expect("Could not create test logs directory");
+    dir.display().to_string()
 }
 
 pub fn log_to_file(print_string: String) {
@@ -1,3 +1,4 @@
+use chrono::{Datelike, Local};
 use serenity::{
     model::channel::Message,
     prelude::Context,
@@ -9,13 +10,19 @@ use std::{
     fs,
 };
 
+mod config;
+mod error;
+use config::{Config, SharedConfig, CONFIG_FILE};
 mod event_handler;
 mod logging;
 use logging::{log, log_to_file, get_date_string};
 mod db;
-use db::{user, get_ranking};
+use db::{backup::{self, BackupFormat}, guild::{self, Guild}, user, get_ranking, TimeWindow, ZitatFilter};
+use error::Error;
 mod discord;
 use discord::{fetch_message_from_id, send_dm};
+mod scheduler;
+mod sync;
 
 pub enum RankingType {
     Said,
@@ -29,74 +36,178 @@ pub enum QAType {
     Assisted,
 }
 
-static mut OVERALL_ZITATE_COUNT: u16 = 0;
-
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 #[tokio::main]
 async fn main() {
     let (ctx_producer, ctx_receiver) = mpsc::channel();
-    let ctx_producer = Arc::new(Mutex::new(ctx_producer));
+    let (scheduler_ctx_producer, scheduler_ctx_receiver) = mpsc::channel();
+    let ctx_producers = Arc::new(Mutex::new(vec![ctx_producer, scheduler_ctx_producer]));
+    fs::create_dir_all("logs").expect("Could not create logs directory");
+    log(&format!("Zitate-Bot v{VERSION}"), "INFO");
+    let config = match Config::load(CONFIG_FILE) {
+        Ok(config) => config,
+        Err(errors) => {
+            for error in errors {
+                log(&error, "ERR ");
+            }
+            panic!("Invalid config");
+        }
+    };
+    if let Err(why) = db::init(&config) {
+        log(&format!("Could not set up database: {why}"), "ERR ");
+        panic!("Invalid database");
+    }
+    let config = SharedConfig::new(config);
+    let console_config = config.clone();
     tokio::spawn(async move {
-        let config = pml::parse::file("config.pml").expect("Error parsing config file");
         let ctx = ctx_receiver.recv().unwrap();
         loop {
             let mut input = String::new();
             io::stdin().read_line(&mut input).unwrap();
-            console_input_handler(input, &ctx, &config).await;
+            console_input_handler(input, &ctx, &console_config).await;
         }
     });
-    fs::create_dir_all("logs").expect("Could not create logs directory");
-    log(&format!("Zitate-Bot v{VERSION}"), "INFO");
-    let config = pml::parse::file("config.pml").expect("Error parsing config file");
-    db::init(&config);
-    unsafe {
-        let connection = db::new_connection();
-        let mut statement = connection.prepare("SELECT count(*) as count FROM zitate").unwrap();
-        let _ = statement.next();
-        OVERALL_ZITATE_COUNT = statement.read::<i64, _>("count").unwrap() as u16;
-    }
-    let mut client = discord::init_client(config, ctx_producer).await;
+    let scheduler_config = config.clone();
+    tokio::spawn(async move {
+        let ctx = tokio::task::spawn_blocking(move || scheduler_ctx_receiver.recv().unwrap()).await.unwrap();
+        scheduler::run(scheduler_config, ctx).await;
+    });
+    let mut client = discord::init_client(config, ctx_producers).await;
     if let Err(why) = client.start().await {
         log(&format!("Could not start client: {:?}", why), "ERR ");
     }
 }
 
-async fn console_input_handler(input: String, ctx: &Context, config: &pml::PmlStruct) {
+async fn console_input_handler(input: String, ctx: &Context, config: &SharedConfig) {
     let input = input.trim();
     log_to_file(format!("[{}] > {input}", get_date_string()));
+    match run_console_command(input, ctx, config).await {
+        Ok(()) => (),
+        Err(why @ Error::UserInput(_)) => println!("{why}"),
+        Err(why) => log(&format!("Console command \"{input}\" failed: {why}"), "ERR "),
+    }
+}
+
+async fn run_console_command(input: &str, ctx: &Context, config: &SharedConfig) -> error::Result<()> {
     let result: Vec<String> = input.split(' ').map(|s| s.to_string()).collect();
-    match result.get(0) {
+    let default_guild_id = config.get().guild_id;
+    match result.first() {
         Some(s) if s == "zitat" => match result.get(1) {
-            Some(s) if s == "add" => register_zitat({
-                fetch_message_from_id(match result.get(2) {
-                    Some(s) => match s.parse::<u64>() {
-                        Ok(id) => id,
-                        Err(_) => {
-                            println!("Invalid message ID");
-                            return;
+            Some(s) if s == "add" => {
+                let default_guild = guild::get(default_guild_id)?
+                    .ok_or_else(|| Error::Config(String::from("Default guild not found in DB")))?;
+                register_zitat({
+                    fetch_message_from_id(match result.get(2) {
+                        Some(s) => match s.parse::<u64>() {
+                            Ok(id) => id,
+                            Err(_) => {
+                                println!("Invalid message ID");
+                                return Ok(());
+                            }
+                        },
+                        None => {
+                            println!("Missing message ID");
+                            return Ok(());
                         }
-                    },
-                    None => {
-                        println!("Missing message ID");
-                        return;
-                    }
-                }, config.get("channelZitate").expect("channelZitate value not found in config file"), ctx,).await.unwrap()
-            }, config, ctx).await,
+                    }, default_guild.channel_zitate, ctx,).await
+                        .ok_or_else(|| Error::UserInput(String::from("Message not found")))?
+                }, &default_guild, ctx).await?
+            },
             Some(s) if s == "remove" => remove_zitat(
                 match result.get(2) {
                     Some(s) => match s.parse::<u64>() {
                         Ok(id) => id,
                         Err(_) => {
                             println!("Invalid message ID");
-                            return;
+                            return Ok(());
                         }
                     },
                     None => {
                         println!("Missing message ID");
-                        return;
+                        return Ok(());
+                    }
+                }, ctx).await?,
+            Some(s) if s == "unattribute" => {
+                let id = match result.get(2).map(|s| s.parse::<u64>()) {
+                    Some(Ok(id)) => id,
+                    Some(Err(_)) => {
+                        println!("Invalid message ID");
+                        return Ok(());
+                    }
+                    None => {
+                        println!("Missing message ID");
+                        return Ok(());
                     }
-                }, ctx, config).await,
+                };
+                let r#type = match result.get(3) {
+                    Some(s) if s == "said" => QAType::Said,
+                    Some(s) if s == "assisted" => QAType::Assisted,
+                    Some(_) => {
+                        println!("Unknown attribution type");
+                        return Ok(());
+                    }
+                    None => {
+                        println!("Missing attribution type");
+                        return Ok(());
+                    }
+                };
+                match result.get(4) {
+                    Some(name) => {
+                        let user = user::get(name)?.ok_or_else(|| format!("{name}: User not found"));
+                        println!("{}", db::remove_qa(r#type, vec![user], id)?);
+                    }
+                    None => println!("Missing user name"),
+                }
+            },
+            Some(s) if s == "restore" => match result.get(2) {
+                Some(s) => match s.parse::<u64>() {
+                    Ok(id) => println!("{}", restore_zitat(id, ctx).await?),
+                    Err(_) => println!("Invalid message ID"),
+                },
+                None => println!("Missing message ID"),
+            },
+            Some(s) if s == "export" || s == "import" => {
+                let path = match result.get(2) {
+                    Some(path) => path,
+                    None => {
+                        println!("Missing path");
+                        return Ok(());
+                    }
+                };
+                let format = match result.get(3).map(|s| s.as_str()) {
+                    Some("json") | Some("--dry-run") | None => BackupFormat::Json,
+                    Some("csv") => BackupFormat::Csv,
+                    Some(_) => {
+                        println!("Unknown format");
+                        return Ok(());
+                    }
+                };
+                if s == "export" {
+                    println!("{}", backup::export(path, format)?);
+                } else {
+                    let dry_run = result.iter().any(|s| s == "--dry-run");
+                    println!("{}", backup::import(path, format, dry_run)?);
+                }
+            },
+            Some(s) if s == "history" => match result.get(2) {
+                Some(s) => match s.parse::<u64>() {
+                    Ok(id) => println!("{}", db::get_zitat_history(id)?),
+                    Err(_) => println!("Invalid message ID"),
+                },
+                None => println!("Missing message ID"),
+            },
+            Some(s) if s == "open" => println!("{}", db::get_open_zitate(default_guild_id)?),
+            Some(s) if s == "sync" => {
+                let dry_run = result.iter().any(|s| s == "--dry-run");
+                for guild in guild::get_all()? {
+                    println!("{}", sync::run(&guild, ctx, true, dry_run).await?);
+                }
+            },
+            Some(s) if s == "random" => {
+                let filter = parse_zitat_filter(default_guild_id, &result[2..])?;
+                println!("{}", db::get_random_zitat(filter)?);
+            },
             Some(_) => println!("Unknown subcommand"),
             None => println!("Missing subcommand"),
         },
@@ -107,30 +218,79 @@ async fn console_input_handler(input: String, ctx: &Context, config: &pml::PmlSt
                         Ok(id) => id,
                         Err(_) => {
                             println!("Invalid user ID");
-                            return;
+                            return Ok(());
                         }
                     },
                     None => {
                         println!("Missing user ID");
-                        return;
+                        return Ok(());
+                    }
+                },
+                match result.get(2) {
+                    Some(s) => s,
+                    None => {
+                        println!("Missing user name");
+                        return Ok(());
+                    }
+                })?,
+            Some(s) if s == "link" => match user::get(
+                match result.get(2) {
+                    Some(s) => s,
+                    None => {
+                        println!("Missing user name");
+                        return Ok(());
+                    }
+                })? {
+                Some(user) => match result.get(3).map(|s| s.parse::<u64>()) {
+                    Some(Ok(id)) => println!("{}", user::link(user, id)?),
+                    Some(Err(_)) => println!("Invalid user ID"),
+                    None => println!("Missing user ID"),
+                },
+                None => println!("User not found"),
+            },
+            Some(s) if s == "unlink" => match result.get(2).map(|s| s.parse::<u64>()) {
+                Some(Ok(id)) => println!("{}", user::unlink(id)?),
+                Some(Err(_)) => println!("Invalid user ID"),
+                None => println!("Missing user ID"),
+            },
+            Some(s) if s == "nick" => match user::get(
+                match result.get(2) {
+                    Some(s) => s,
+                    None => {
+                        println!("Missing user name");
+                        return Ok(());
                     }
+                })? {
+                Some(user) => match result.get(3) {
+                    Some(alias) => println!("{}", user::add_nickname(user, alias)?),
+                    None => println!("Missing nickname"),
                 },
+                None => println!("User not found"),
+            },
+            Some(s) if s == "unnick" => match result.get(2) {
+                Some(alias) => println!("{}", user::remove_nickname(alias)?),
+                None => println!("Missing nickname"),
+            },
+            Some(s) if s == "aliases" => match user::get(
                 match result.get(2) {
                     Some(s) => s,
                     None => {
                         println!("Missing user name");
-                        return;
+                        return Ok(());
                     }
-                }),
+                })? {
+                Some(user) => println!("{}", user::get_aliases(user)?),
+                None => println!("User not found"),
+            },
             Some(s) if s == "stats" => match user::get(
                 match result.get(2) {
                     Some(s) => s,
                     None => {
                         println!("Missing user name");
-                        return;
+                        return Ok(());
                     }
-                }) {
-                Some(user) => println!("{}", user::get_stats(user)),
+                })? {
+                Some(user) => println!("{}", user::get_stats(user, default_guild_id)?),
                 None => println!("User not found"),
             },
             Some(s) if s == "ranking" => {
@@ -140,38 +300,87 @@ async fn console_input_handler(input: String, ctx: &Context, config: &pml::PmlSt
                     Some(s) if s == "assisted" => RankingType::Assisted,
                     Some(_) => {
                         println!("Unknown ranking type");
-                        return;
+                        return Ok(());
                     }
                     None => {
                         println!("Missing ranking type");
-                        return;
+                        return Ok(());
                     }
                 };
-                println!("{}", get_ranking(r#type));
+                let window = parse_time_window(&result[3..]).map_err(Error::UserInput)?;
+                println!("{}", get_ranking(r#type, default_guild_id, &window)?);
             },
             Some(s) if s == "message" => send_dm(
                 match user::get_id(match result.get(2) {
                     Some(s) => s,
                     None => {
                         println!("Missing user");
-                        return;
+                        return Ok(());
                     }
-                }) {
+                })? {
                     Some(id) => id,
                     None => {
                         println!("Invalid username");
-                        return;
+                        return Ok(());
                     }
                 },
                 match result.get(3) {
                     Some(text) => text.clone(),
                     None => {
                         println!("Missing message");
-                        return
+                        return Ok(())
                     }
                 },
                 ctx
-                ).await,
+                ).await?,
+            Some(_) => println!("Unknown subcommand"),
+            None => println!("Missing subcommand"),
+        },
+        Some(s) if s == "guild" => match result.get(1) {
+            Some(s) if s == "add" => {
+                let ids: Result<Vec<u64>, _> = result[2..].iter().map(|s| s.parse::<u64>()).collect();
+                match ids.as_deref() {
+                    Ok([id, channel_zitate, channel_bot, rest @ ..]) if rest.len() <= 1 => {
+                        println!("{}", guild::set(&Guild {
+                            id: *id,
+                            channel_zitate: *channel_zitate,
+                            channel_bot: *channel_bot,
+                            channel_daily: rest.first().copied(),
+                        })?);
+                        event_handler::register_commands(*id, ctx).await;
+                    }
+                    Ok(_) => println!("Usage: guild add <guild ID> <Zitate channel ID> <bot channel ID> [daily channel ID]"),
+                    Err(_) => println!("Invalid ID"),
+                }
+            },
+            Some(s) if s == "remove" => match result.get(2).map(|s| s.parse::<u64>()) {
+                Some(Ok(id)) if id == default_guild_id => println!("The guild from the config file cannot be removed"),
+                Some(Ok(id)) => println!("{}", guild::remove(id)?),
+                Some(Err(_)) => println!("Invalid guild ID"),
+                None => println!("Missing guild ID"),
+            },
+            Some(s) if s == "list" => println!("{}", guild::list()?),
+            Some(_) => println!("Unknown subcommand"),
+            None => println!("Missing subcommand"),
+        },
+        Some(s) if s == "config" => match result.get(1) {
+            Some(s) if s == "reload" => match config.reload(CONFIG_FILE, |new_config, changed| {
+                if changed.iter().any(|key| ["channelZitate", "channelBot", "channelDaily"].contains(key)) {
+                    db::set_default_guild(new_config).map_err(|why| why.to_string())?;
+                }
+                Ok(())
+            }) {
+                Ok(changed) if changed.is_empty() => println!("Config reloaded, nothing changed"),
+                Ok(changed) => {
+                    log(&format!("Config reloaded, changed: {}", changed.join(", ")), "INFO");
+                }
+                Err(errors) => {
+                    println!("Config not reloaded:");
+                    for error in errors {
+                        println!("{error}");
+                    }
+                }
+            },
             Some(_) => println!("Unknown subcommand"),
             None => println!("Missing subcommand"),
         },
@@ -190,25 +399,112 @@ async fn console_input_handler(input: String, ctx: &Context, config: &pml::PmlSt
         Some(_) => println!("Unknown command"),
         None => (),
     }
+    Ok(())
+}
+
+fn parse_zitat_filter(guild: u64, args: &[String]) -> error::Result<ZitatFilter> {
+    let mut filter = ZitatFilter {
+        guild,
+        ..Default::default()
+    };
+    let mut window_args = Vec::new();
+    for pair in args.chunks(2) {
+        let value = match pair.get(1) {
+            Some(value) => value,
+            None => return Err(Error::UserInput(format!("Missing value for {}", pair[0]))),
+        };
+        match pair[0].as_str() {
+            "said" | "wrote" => {
+                let id = match user::get_id(value)? {
+                    Some(id) => id,
+                    None => return Err(Error::UserInput(String::from("User not found"))),
+                };
+                if pair[0] == "said" {
+                    filter.said = Some(id);
+                } else {
+                    filter.writer = Some(id);
+                }
+            }
+            _ => window_args.extend_from_slice(pair),
+        }
+    }
+    filter.window = parse_time_window(&window_args).map_err(Error::UserInput)?;
+    Ok(filter)
+}
+
+fn parse_time_window(args: &[String]) -> Result<TimeWindow, String> {
+    let mut year = None;
+    let mut month = None;
+    let mut window = TimeWindow::default();
+    for pair in args.chunks(2) {
+        let value = match pair.get(1) {
+            Some(value) => value,
+            None => return Err(format!("Missing value for {}", pair[0])),
+        };
+        match pair[0].as_str() {
+            "year" => year = Some(value.parse::<i32>().map_err(|_| String::from("Invalid year"))?),
+            "month" => month = Some(value.parse::<u32>().map_err(|_| String::from("Invalid month"))?),
+            "from" => window.from = Some(TimeWindow::parse_date(value).ok_or_else(|| String::from("Invalid date (format: DD.MM.YYYY)"))?),
+            "to" => window.to = Some(TimeWindow::parse_date(value).ok_or_else(|| String::from("Invalid date (format: DD.MM.YYYY)"))?),
+            _ => return Err(format!("Unknown filter {}", pair[0])),
+        }
+    }
+    if year.is_none() && month.is_none() {
+        return Ok(window);
+    }
+    if window.from.is_some() || window.to.is_some() {
+        return Err(String::from("Cannot combine year/month with from/to"));
+    }
+    let year = year.unwrap_or_else(|| Local::now().year());
+    match month {
+        Some(month) => TimeWindow::month(year, month),
+        None => TimeWindow::year(year),
+    }.ok_or_else(|| String::from("Invalid time window"))
 }
 
 async fn remove_zitat(
     msg_id: u64,
     ctx: &Context,
-    config: &pml::PmlStruct,
-) {
+) -> error::Result<()> {
+    let guild = guild::get_for_zitat(msg_id)?;
     log(&format!("Deleting Zitat with ID {msg_id}"), "WARN");
-    db::delete_zitat(msg_id);
-    discord::delete_qa_thread(msg_id.to_string(), ctx, config).await;
-    unsafe {
-        OVERALL_ZITATE_COUNT -= 1;
+    db::delete_zitat(msg_id)?;
+    if let Some(guild) = guild {
+        discord::delete_qa_thread(msg_id, ctx, &guild).await?;
     }
+    Ok(())
 }
 
-async fn register_zitat(zitat_msg: Message, config: &pml::PmlStruct, ctx: &Context) {
-    unsafe {
-        OVERALL_ZITATE_COUNT += 1;
+async fn restore_zitat(msg_id: u64, ctx: &Context) -> error::Result<String> {
+    if !db::restore_zitat(msg_id)? {
+        return Ok(String::from("No deleted Zitat with this ID found"));
+    }
+    let Some(guild) = guild::get_for_zitat(msg_id)? else {
+        return Ok(String::from("Zitat restored"));
+    };
+    if !db::qa_thread::get_unattributed(guild.id)?.contains(&msg_id) {
+        return Ok(String::from("Zitat restored"));
+    }
+    match fetch_message_from_id(msg_id, guild.channel_zitate, ctx).await {
+        Some(msg) => {
+            discord::create_qa_thread(&msg, &guild, ctx).await?;
+            Ok(String::from("Zitat restored, QA thread recreated"))
+        }
+        None => Ok(String::from("Zitat restored, but it has no QA thread since its message is gone")),
     }
-    db::insert_zitat(&zitat_msg);
-    discord::create_qa_thread(&zitat_msg, config, ctx).await;
+}
+
+async fn mark_zitat_deleted(
+    msg_id: u64,
+    ctx: &Context,
+    guild: &Guild,
+) -> error::Result<()> {
+    log(&format!("Zitat with ID {msg_id} was deleted, marking it as deleted"), "WARN");
+    db::mark_zitat_deleted(msg_id)?;
+    discord::delete_qa_thread(msg_id, ctx, guild).await
+}
+
+async fn register_zitat(zitat_msg: Message, guild: &Guild, ctx: &Context) -> error::Result<()> {
+    db::insert_zitat(&zitat_msg, guild.id)?;
+    discord::create_qa_thread(&zitat_msg, guild, ctx).await
 }
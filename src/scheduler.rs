@@ -0,0 +1,166 @@
+use std::{collections::HashMap, time::Duration};
+
+use chrono::{Local, NaiveDate, TimeDelta};
+use serenity::{model::id::ChannelId, prelude::Context};
+
+use crate::{config::SharedConfig, db::{daily, get_formatted_zitat, guild, qa_thread}, discord::{send_dm, split_message, ThreadLookup}, error::Result, logging::log};
+
+// Wake up at least this often so a reloaded config takes effect
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+pub async fn run(config: SharedConfig, ctx: Context) {
+    tokio::join!(
+        run_daily_posts(&config, &ctx),
+        run_reminders(&config, &ctx)
+    );
+}
+
+async fn run_daily_posts(config: &SharedConfig, ctx: &Context) {
+    if config.get().daily_time.is_none() {
+        log("dailyTime value not found in config file, Zitat des Tages disabled", "WARN");
+    }
+    loop {
+        let daily_time = match config.get().daily_time {
+            Some(time) => time,
+            None => {
+                tokio::time::sleep(CHECK_INTERVAL).await;
+                continue;
+            }
+        };
+        let now = Local::now().naive_local();
+        let today_at = now.date().and_time(daily_time);
+        if now >= today_at {
+            let guilds = guild::get_all().unwrap_or_else(|why| {
+                log(&format!("Could not load guilds for Zitat des Tages: {why}"), "ERR ");
+                Vec::new()
+            });
+            for guild in guilds {
+                let channel_id = match guild.channel_daily {
+                    Some(id) => ChannelId(id),
+                    None => continue,
+                };
+                if let Err(why) = post_daily_zitat(guild.id, &now.date().to_string(), channel_id, ctx).await {
+                    log(&format!("Could not post Zitat des Tages in guild {}: {why}", guild.id), "ERR ");
+                }
+                if let Err(why) = post_anniversary_zitate(guild.id, now.date(), channel_id, ctx).await {
+                    log(&format!("Could not post An diesem Tag in guild {}: {why}", guild.id), "ERR ");
+                }
+            }
+        }
+        let next_run = if now < today_at {
+            today_at
+        } else {
+            today_at + TimeDelta::days(1)
+        };
+        tokio::time::sleep((next_run - now).to_std().unwrap_or_default().min(CHECK_INTERVAL)).await;
+    }
+}
+
+async fn post_daily_zitat(guild: u64, date: &str, channel_id: ChannelId, ctx: &Context) -> Result<()> {
+    if daily::is_posted(guild, date)? {
+        return Ok(());
+    }
+    match daily::pick_zitat(guild)? {
+        Some((zitat_id, cycle)) => {
+            if let Some(zitat) = get_formatted_zitat(zitat_id)? {
+                channel_id.say(&ctx.http, format!("Zitat des Tages:\n{zitat}")).await?;
+                daily::mark_posted(date, zitat_id, cycle)?;
+            }
+        }
+        None => log(&format!("No Zitat available for Zitat des Tages in guild {guild}"), "WARN"),
+    }
+    Ok(())
+}
+
+async fn post_anniversary_zitate(guild: u64, date: NaiveDate, channel_id: ChannelId, ctx: &Context) -> Result<()> {
+    let zitate = match daily::get_anniversary_zitate(guild, date)? {
+        Some(zitate) => zitate,
+        None => return Ok(()),
+    };
+    let date = date.to_string();
+    if daily::is_anniversary_posted(guild, &date)? {
+        return Ok(());
+    }
+    for (i, message) in split_message(zitate).into_iter().enumerate() {
+        channel_id.say(&ctx.http, message).await?;
+        // Once a part is out, a retry would post it twice
+        if i == 0 {
+            daily::mark_anniversary_posted(guild, &date)?;
+        }
+    }
+    Ok(())
+}
+
+async fn run_reminders(config: &SharedConfig, ctx: &Context) {
+    if config.get().reminder_days.is_none() {
+        log("reminderDays value not found in config file, reminders for open threads disabled", "WARN");
+    }
+    loop {
+        let current = config.get();
+        if let Some(days) = current.reminder_days
+            && let Err(why) = remind_writers(days, ctx).await
+        {
+            log(&format!("Could not remind writers of open threads: {why}"), "ERR ");
+        }
+        if let Some(days) = current.owner_reminder_days
+            && let Err(why) = notify_owner(current.owner_id, days, ctx).await
+        {
+            log(&format!("Could not notify the owner about open threads: {why}"), "ERR ");
+        }
+        tokio::time::sleep(CHECK_INTERVAL).await;
+    }
+}
+
+async fn remind_writers(days: u64, ctx: &Context) -> Result<()> {
+    let mut lookups: HashMap<u64, ThreadLookup> = HashMap::new();
+    for (zitat_id, writer_id) in qa_thread::get_due_writer_reminders(days)? {
+        let thread_id = match guild::get_for_zitat(zitat_id)? {
+            Some(guild) => lookups
+                .entry(guild.id)
+                .or_insert_with(|| ThreadLookup::new(&guild, true))
+                .find(zitat_id, ctx)
+                .await?,
+            None => None,
+        };
+        let Some(thread_id) = thread_id else {
+            // Without a thread there is nowhere to remind the writer, so don't look it up again every hour
+            qa_thread::mark_writer_reminded(zitat_id)?;
+            log(&format!("No thread found to remind the writer of Zitat with ID {zitat_id}"), "WARN");
+            continue;
+        };
+        let reminder = format!("<@{writer_id}> Wer hat das gesagt? Bitte mit /gesagt eintragen und den Thread mit /fertig schließen.");
+        match thread_id.say(&ctx.http, reminder).await {
+            Ok(_) => {
+                qa_thread::mark_writer_reminded(zitat_id)?;
+                log(&format!("Reminded writer of Zitat with ID {zitat_id}"), "INFO");
+            }
+            Err(why) => log(&format!("Could not remind writer of Zitat with ID {zitat_id}: {why}"), "ERR "),
+        }
+    }
+    Ok(())
+}
+
+async fn notify_owner(owner_id: u64, days: u64, ctx: &Context) -> Result<()> {
+    let zitate = qa_thread::get_due_owner_reminders(days)?;
+    if zitate.is_empty() {
+        return Ok(());
+    }
+    let mut links = Vec::new();
+    for zitat_id in &zitate {
+        links.extend(get_formatted_zitat(*zitat_id)?);
+    }
+    let message = format!(
+        "Diese Zitate haben seit über {days} Tagen niemanden, der sie gesagt hat:\n{}",
+        links.join("\n------------------\n")
+    );
+    // Marked up front so a part that cannot be sent doesn't make the owner get the others again every hour
+    for zitat_id in &zitate {
+        qa_thread::mark_owner_notified(*zitat_id)?;
+    }
+    for part in split_message(message) {
+        if let Err(why) = send_dm(owner_id, part, ctx).await {
+            log(&format!("Could not send part of the open threads notification to the owner: {why}"), "ERR ");
+        }
+    }
+    Ok(())
+}
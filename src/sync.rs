@@ -0,0 +1,112 @@
+use std::collections::HashSet;
+
+use serenity::{
+    model::{channel::Message, id::ChannelId, prelude::MessageType},
+    prelude::Context,
+};
+
+use crate::{
+    db::{self, guild::Guild, qa_thread},
+    discord::{create_qa_thread, fetch_message_from_id, ThreadLookup},
+    error::Result,
+    logging::log,
+    mark_zitat_deleted,
+    register_zitat,
+};
+
+async fn fetch_channel_history(channel_id: ChannelId, mut after: u64, ctx: &Context) -> serenity::Result<Vec<Message>> {
+    let mut history = Vec::new();
+    loop {
+        let batch = channel_id.messages(&ctx.http, |request| request.after(after).limit(100)).await?;
+        let batch_len = batch.len();
+        if let Some(newest) = batch.iter().map(|msg| msg.id.0).max() {
+            after = newest;
+        }
+        history.extend(batch);
+        if batch_len < 100 {
+            return Ok(history);
+        }
+    }
+}
+
+/// Inserts Zitate posted while the bot was offline and recreates missing QA threads.
+/// Only a `full` sync reads the whole channel history and marks Zitate whose message is gone as deleted,
+/// the sync on startup only pages from the newest known Zitat.
+pub async fn run(guild: &Guild, ctx: &Context, full: bool, dry_run: bool) -> Result<String> {
+    let channel_id = guild.channel_zitate;
+    let newest_id = db::get_newest_zitat_id(guild.id)?.unwrap_or(0);
+    let after = if full { 0 } else { newest_id };
+    let history = match fetch_channel_history(ChannelId(channel_id), after, ctx).await {
+        Ok(history) => history,
+        Err(why) => {
+            log(&format!("Could not fetch history of the Zitate channel of guild {}: {why}", guild.id), "ERR ");
+            return Ok(format!("Sync of guild {} aborted, could not fetch history of the Zitate channel", guild.id));
+        }
+    };
+    let existing: HashSet<u64> = history.iter().map(|msg| msg.id.0).collect();
+    let mut missing: Vec<Message> = history
+        .into_iter()
+        .filter(|msg| msg.id.0 > newest_id && !msg.author.bot && msg.kind == MessageType::Regular)
+        .collect();
+    missing.sort_by_key(|msg| msg.id.0);
+    let vanished: Vec<u64> = if full {
+        db::get_active_zitat_ids(guild.id, channel_id)?
+            .into_iter()
+            .filter(|id| !existing.contains(id))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let mut report = Vec::new();
+    let (insert, delete, create) = if dry_run {
+        ("Would insert", "Would mark as deleted", "Would recreate thread for")
+    } else {
+        ("Inserted", "Marked as deleted", "Recreated thread for")
+    };
+    let inserted = missing.len();
+    for msg in missing {
+        report.push(format!("{insert} Zitat with ID {}", msg.id.0));
+        if !dry_run {
+            register_zitat(msg, guild, ctx).await?;
+        }
+    }
+    for id in &vanished {
+        report.push(format!("{delete} Zitat with ID {id}"));
+        if !dry_run {
+            mark_zitat_deleted(*id, ctx, guild).await?;
+        }
+    }
+
+    let mut recreated = 0;
+    let mut threads = ThreadLookup::new(guild, !dry_run);
+    for id in qa_thread::get_unattributed(guild.id)? {
+        if vanished.contains(&id) {
+            continue;
+        }
+        if let Some(thread_id) = threads.find(id, ctx).await? {
+            if ctx.http.get_channel(thread_id.0).await.is_ok() {
+                continue;
+            }
+            if !dry_run {
+                qa_thread::delete(id)?;
+            }
+        }
+        recreated += 1;
+        report.push(format!("{create} Zitat with ID {id}"));
+        if !dry_run {
+            match fetch_message_from_id(id, channel_id, ctx).await {
+                Some(msg) => create_qa_thread(&msg, guild, ctx).await?,
+                None => log(&format!("Could not fetch message for Zitat with ID {id}"), "ERR "),
+            }
+        }
+    }
+
+    report.push(format!(
+        "Sync of guild {}{}: {inserted} new, {} deleted, {recreated} missing threads",
+        guild.id,
+        if dry_run { " (dry run)" } else { "" },
+        vanished.len()
+    ));
+    Ok(report.join("\n"))
+}